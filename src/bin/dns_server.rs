@@ -1,6 +1,99 @@
+use std::env;
+use std::process;
+
 extern crate dns_server;
-use dns_server::web::server::main as dns_server_main;
+use dns_server::web::config::Config;
+use dns_server::web::server::{run, DefaultHandler};
+
+const USAGE: &str = "\
+Usage: dns_server [options]
+
+Options:
+  -c, --config <file>           Read settings from a TOML file
+      --listen <addrs>          Addresses to listen on, e.g. 0.0.0.0:2053
+      --mode <mode>             recursive or forwarding
+      --root-servers <addrs>    Root servers to start recursive lookups at
+      --forwarders <addrs>      Resolvers to forward queries to
+      --randomize-case <bool>   Randomize the case of outgoing queries
+      --timeout-ms <n>          Time to wait for a server to answer a query
+      --retries <n>             Times to resend a query that timed out
+      --time-budget-ms <n>      Time allowed to resolve each client query
+      --workers <n>             Threads answering UDP queries
+      --max-tcp <n>             TCP connections open at once
+      --max-resolutions <n>     Recursive resolutions running at once
+      --cache-size <n>          Responses to cache, 0 to disable the cache
+      --log-level <level>       error, info or debug
+      --allow <subnets>         Clients to answer, e.g. 127.0.0.0/8
+  -h, --help                    Print this message
+
+Lists are comma separated. Options given on the command line override the
+config file.";
+
+/// The command line options that override a config file setting.
+const OVERRIDES: &[(&str, &str)] = &[
+    ("--listen", "server.listen"),
+    ("--mode", "resolver.mode"),
+    ("--root-servers", "resolver.root_servers"),
+    ("--forwarders", "resolver.forwarders"),
+    ("--randomize-case", "resolver.randomize_case"),
+    ("--timeout-ms", "resolver.timeout_ms"),
+    ("--retries", "resolver.retries"),
+    ("--time-budget-ms", "resolver.time_budget_ms"),
+    ("--workers", "server.worker_threads"),
+    ("--max-tcp", "server.max_tcp_connections"),
+    ("--max-resolutions", "server.max_recursive_resolutions"),
+    ("--cache-size", "cache.size"),
+    ("--log-level", "log.level"),
+    ("--allow", "acl.allow"),
+];
+
+fn fail(message: &str) -> ! {
+    eprintln!("dns_server: {}", message);
+    process::exit(1);
+}
 
 fn main() {
-    dns_server_main();
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // The config file is read first, wherever it appears, so that the other
+    // options can override it.
+    let mut config_path = None;
+    let mut overrides = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if arg == "-h" || arg == "--help" {
+            println!("{}", USAGE);
+            return;
+        }
+
+        let value = match args.get(i + 1) {
+            Some(x) => x.as_str(),
+            None => fail(&format!("{} needs a value\n\n{}", arg, USAGE)),
+        };
+        if arg == "-c" || arg == "--config" {
+            config_path = Some(value);
+        } else {
+            match OVERRIDES.iter().find(|&&(flag, _)| flag == arg) {
+                Some(&(_, key)) => overrides.push((key, value)),
+                None => fail(&format!("Unknown option {}\n\n{}", arg, USAGE)),
+            }
+        }
+        i += 2;
+    }
+
+    let mut config = match config_path {
+        Some(path) => Config::from_file(path).unwrap_or_else(|e| fail(&e.to_string())),
+        None => Config::new(),
+    };
+    for (key, value) in overrides {
+        if let Err(e) = config.set(key, value) {
+            fail(&e.to_string());
+        }
+    }
+    let options = config.to_options().unwrap_or_else(|e| fail(&e.to_string()));
+
+    if let Err(e) = run(&DefaultHandler, &options) {
+        fail(&e.to_string());
+    }
+}
@@ -1,4 +1,5 @@
-use std::net::UdpSocket;
+use std::env;
+use std::net::{IpAddr, UdpSocket};
 
 /// Since this is an another binary, it is not part of the dns-server's crate
 /// module structure. Given this, we need to declare that we are using dns-server
@@ -6,28 +7,38 @@ use std::net::UdpSocket;
 extern crate dns_server;
 use dns_server::dns::byte_packet_buffer::BytePacketBuffer;
 use dns_server::dns::dns_packet::DnsPacket;
-use dns_server::dns::dns_question::DnsQuestion;
+use dns_server::dns::edns::DEFAULT_EDNS_PAYLOAD_SIZE;
+use dns_server::dns::name::Name;
 use dns_server::dns::query_type::QueryType;
+use dns_server::dns::reverse::ip_to_reverse_name;
+use dns_server::dns::vector_packet_buffer::VectorPacketBuffer;
 
 fn main() {
-    // Perform an A query for google.com
-    let qname = "yahoo.com";
-    let qtype = QueryType::MX;
+    // Perform an MX query for yahoo.com, or a reverse (PTR) lookup when
+    // invoked as `stub_resolver -x <address>`.
+    let args: Vec<String> = env::args().collect();
+    let (qname, qtype) = if args.len() == 3 && args[1] == "-x" {
+        let addr = match args[2].parse::<IpAddr>() {
+            Ok(x) => x,
+            Err(e) => {
+                eprintln!("Invalid address {}: {}", args[2], e);
+                return;
+            }
+        };
+        (ip_to_reverse_name(addr), QueryType::PTR)
+    } else {
+        ("yahoo.com".parse::<Name>().unwrap(), QueryType::MX)
+    };
 
     // Use google's public DNS server
     let server = ("8.8.8.8", 53);
 
     // open a socket to communicate with the DNS server
-    let socket = UdpSocket::bind(("0.0.0.0", 43210)).unwrap();
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).unwrap();
 
-    // Build query packet.
-    let mut packet = DnsPacket::new();
-    packet.header.id = 6666;
-    packet.header.questions = 1;
-    packet.header.recursion_desired = true;
-    packet
-        .questions
-        .push(DnsQuestion::new(qname.to_string(), qtype));
+    // Build query packet. EDNS0 allows the server to explain failures with
+    // Extended DNS Errors.
+    let mut packet = DnsPacket::query(&qname, qtype).with_edns(DEFAULT_EDNS_PAYLOAD_SIZE, false);
 
     // write packet to a buffer
     let mut req_buffer = BytePacketBuffer::new();
@@ -39,22 +50,27 @@ fn main() {
         .unwrap();
 
     // Create a packet to receive the response
-    let mut res_buffer = BytePacketBuffer::new();
-    socket.recv_from(&mut res_buffer.buf).unwrap();
+    let mut recv_buf = [0; DEFAULT_EDNS_PAYLOAD_SIZE as usize];
+    let (len, _) = socket.recv_from(&mut recv_buf).unwrap();
+    let mut res_buffer = VectorPacketBuffer::from_bytes(&recv_buf[..len]);
 
     let res_packet = DnsPacket::from_buffer(&mut res_buffer).unwrap();
 
     println!("{:#?}", res_packet.header);
+    println!("rcode: {}", res_packet.rescode());
+    for err in res_packet.extended_errors() {
+        println!("; EDE: {}", err);
+    }
     for q in res_packet.questions {
-        println!("{:#?}", q);
+        println!(";{}", q);
     }
     for rec in res_packet.answers {
-        println!("{:#?}", rec);
+        println!("{}", rec);
     }
     for rec in res_packet.authorities {
-        println!("{:#?}", rec);
+        println!("{}", rec);
     }
     for rec in res_packet.resources {
-        println!("{:#?}", rec);
+        println!("{}", rec);
     }
 }
@@ -1,8 +1,17 @@
-use std::io::{Error, ErrorKind, Result};
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::packet_buffer::{NameCompression, PacketBuffer};
 
+/// A fixed 512 byte buffer, the maximum size of a plain UDP DNS message.
 pub struct BytePacketBuffer {
     pub buf: [u8; 512],
     pub pos: usize,
+    names: NameCompression,
+}
+
+impl Default for BytePacketBuffer {
+    fn default() -> Self {
+        BytePacketBuffer::new()
+    }
 }
 
 impl BytePacketBuffer {
@@ -12,29 +21,37 @@ impl BytePacketBuffer {
         BytePacketBuffer {
             buf: [0; 512],
             pos: 0,
+            names: NameCompression::new(),
         }
     }
 
-    pub fn pos(&self) -> usize {
+    /// Enables or disables name compression, as `NameCompression::set_enabled`.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.names.set_enabled(enabled);
+    }
+}
+
+impl PacketBuffer for BytePacketBuffer {
+    fn pos(&self) -> usize {
         self.pos
     }
 
-    pub fn step(&mut self, steps: usize) -> Result<()> {
-        self.pos += steps;
-        Ok(())
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.seek(self.pos.saturating_add(steps))
     }
 
-    pub fn seek(&mut self, pos: usize) -> Result<()> {
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos > 512 {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
+        }
         self.pos = pos;
         Ok(())
     }
 
-    /// Methods for reading 1, 2, or 4 bytes.
-
-    pub fn read(&mut self) -> Result<u8> {
+    fn read(&mut self) -> Result<u8> {
         // check if we're at/past the end of buffer
         if self.pos >= 512 {
-            return Err(Error::new(ErrorKind::InvalidInput, "End of buffer"));
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, self.pos));
         }
 
         let res = self.buf[self.pos];
@@ -43,157 +60,106 @@ impl BytePacketBuffer {
         Ok(res)
     }
 
-    pub fn read_u16(&mut self) -> Result<u16> {
-        let res = ((self.read()? as u16) << 8) | (self.read()? as u16);
-        Ok(res)
-    }
-
-    pub fn read_u32(&mut self) -> Result<u32> {
-        let res = ((self.read()? as u32) << 24)
-            | ((self.read()? as u32) << 16)
-            | ((self.read()? as u32) << 8)
-            | ((self.read()? as u32) << 0);
-        Ok(res)
-    }
-
-    /// Methods for writing
-
     fn write(&mut self, val: u8) -> Result<()> {
         if self.pos >= 512 {
-            return Err(Error::new(ErrorKind::InvalidInput, "End of buffer"));
+            return Err(DnsError::new(DnsErrorKind::BufferFull, self.pos));
         }
         self.buf[self.pos] = val;
         self.pos += 1;
         Ok(())
     }
 
-    pub fn write_u8(&mut self, val: u8) -> Result<()> {
-        self.write(val)?;
-        Ok(())
-    }
-
-    pub fn write_u16(&mut self, val: u16) -> Result<()> {
-        self.write((val >> 8) as u8)?;
-        self.write((val & 0xFF) as u8)?;
-        Ok(())
-    }
-
-    pub fn write_u32(&mut self, val: u32) -> Result<()> {
-        self.write(((val >> 24) & 0xFF) as u8)?;
-        self.write(((val >> 16) & 0xFF) as u8)?;
-        self.write(((val >> 8) & 0xFF) as u8)?;
-        self.write(((val >> 0) & 0xFF) as u8)?;
-
-        Ok(())
-    }
-
-    /// Methods for setting
-
     fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+        if pos >= 512 {
+            return Err(DnsError::new(DnsErrorKind::BufferFull, pos));
+        }
         self.buf[pos] = val;
 
         Ok(())
     }
 
-    pub fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
-        self.set(pos, (val >> 8) as u8)?;
-        self.set(pos + 1, (val & 0xFF) as u8)?;
+    // Methods for fetching data at a specified position or range without
+    // modifying the internal position.
 
-        Ok(())
-    }
-
-    /// Methods for fetching data at a specified position or range without
-    /// modifying the internal position.
-
-    pub fn get(&mut self, pos: usize) -> Result<u8> {
+    fn get(&mut self, pos: usize) -> Result<u8> {
         if pos >= 512 {
-            return Err(Error::new(ErrorKind::InvalidInput, "End of buffer"));
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
         }
         Ok(self.buf[pos])
     }
 
-    pub fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
-        if start + len >= 512 {
-            return Err(Error::new(ErrorKind::InvalidInput, "End of buffer"));
+    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
+        if start.saturating_add(len) > 512 {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, start));
         }
-        Ok(&self.buf[start..start + len as usize])
+        Ok(&self.buf[start..start + len])
     }
 
-    /// The tricky part is in reading domain names and taking labels into
-    /// consideration.
-    pub fn read_qname(&mut self, outstr: &mut String) -> Result<()> {
-        let mut pos = self.pos();
-        let mut jumped = false;
-
-        // A delimiter that is appended for each label.
-        let mut delim = "";
-        loop {
-            let len = self.get(pos)?;
-
-            // If `len` has the 2 most significant bits set, it represents a jump
-            // to some other offset in this packet.
-            if (len & 0xC0) == 0xC0 {
-                // Update the buffer position to a point past the current label.
-                if !jumped {
-                    self.seek(pos + 2)?;
-                }
-
-                // Read another byte, calculate the offset and perform the jump
-                // by updating our local position variable.
-                let b2 = self.get(pos + 1)? as u16;
-                let offset = (((len as u16) ^ 0xC0) << 8) | b2;
-                pos = offset as usize;
-
-                jumped = true;
-            } else {
-                pos += 1;
-
-                // Domain names are terminated by an empty label of length 0.
-                if len == 0 {
-                    break;
-                }
-
-                outstr.push_str(delim);
-
-                let str_buffer = self.get_range(pos, len as usize)?;
-                outstr.push_str(&String::from_utf8_lossy(str_buffer).to_lowercase());
-
-                delim = ".";
-
-                // Move forward the full length of the label.
-                pos += len as usize;
-            }
-        }
-
-        // If a jump was performed, we've already modified the buffer position state
-        // and shouldn't do it again.
-        if !jumped {
-            self.seek(pos)?;
-        }
-
-        Ok(())
+    fn find_label(&self, label: &str) -> Option<usize> {
+        self.names.find(label)
     }
 
-    pub fn write_qname(&mut self, qname: &str) -> Result<()> {
-        let split_str = qname.split('.').collect::<Vec<&str>>();
-
-        for label in split_str {
-            let len = label.len();
-            if len > 0x344 {
-                return Err(Error::new(
-                    ErrorKind::InvalidInput,
-                    "Single label exceeds 63 characters of length",
-                ));
-            }
-
-            self.write_u8(len as u8)?;
-            for b in label.as_bytes() {
-                self.write_u8(*b)?;
-            }
-        }
+    fn save_label(&mut self, label: &str, pos: usize) {
+        self.names.save(label, pos);
+    }
 
-        self.write_u8(0)?;
+    fn compression(&self) -> bool {
+        self.names.is_enabled()
+    }
+}
 
-        Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind<T>(res: Result<T>) -> DnsErrorKind {
+        res.err().unwrap().kind
+    }
+
+    #[test]
+    fn seek_and_step_stop_at_the_end() {
+        let mut buffer = BytePacketBuffer::new();
+        buffer.seek(512).unwrap();
+        assert_eq!(kind(buffer.seek(513)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(buffer.pos(), 512);
+
+        buffer.seek(500).unwrap();
+        buffer.step(12).unwrap();
+        buffer.seek(500).unwrap();
+        assert_eq!(kind(buffer.step(13)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(kind(buffer.step(usize::MAX)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(buffer.pos(), 500);
+    }
+
+    #[test]
+    fn reads_stop_at_the_end() {
+        let mut buffer = BytePacketBuffer::new();
+        buffer.seek(511).unwrap();
+        buffer.read().unwrap();
+        assert_eq!(kind(buffer.read()), DnsErrorKind::EndOfBuffer);
+
+        buffer.seek(510).unwrap();
+        assert_eq!(kind(buffer.read_u32()), DnsErrorKind::EndOfBuffer);
+
+        assert_eq!(kind(buffer.get(512)), DnsErrorKind::EndOfBuffer);
+        assert!(buffer.get_range(500, 12).is_ok());
+        assert_eq!(kind(buffer.get_range(500, 13)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(
+            kind(buffer.get_range(1, usize::MAX)),
+            DnsErrorKind::EndOfBuffer
+        );
+    }
+
+    #[test]
+    fn writes_stop_at_the_end() {
+        let mut buffer = BytePacketBuffer::new();
+        buffer.seek(511).unwrap();
+        buffer.write(1).unwrap();
+        assert_eq!(kind(buffer.write(2)), DnsErrorKind::BufferFull);
+
+        buffer.set(511, 3).unwrap();
+        assert_eq!(buffer.buf[511], 3);
+        assert_eq!(kind(buffer.set(512, 4)), DnsErrorKind::BufferFull);
+        assert_eq!(kind(buffer.set_u16(511, 5)), DnsErrorKind::BufferFull);
     }
 }
@@ -0,0 +1,142 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use super::packet_buffer::{MAX_JUMPS, MAX_NAME_LEN};
+
+pub type Result<T> = std::result::Result<T, DnsError>;
+
+/// What went wrong while parsing or serializing a DNS message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsErrorKind {
+    /// The message ended before the data we were reading did.
+    EndOfBuffer,
+    /// The message doesn't fit in the buffer it is being written to.
+    BufferFull,
+    /// A label is longer than 63 bytes.
+    LabelTooLong,
+    /// A name is longer than `MAX_NAME_LEN` bytes in wire format.
+    NameTooLong,
+    /// More than `MAX_JUMPS` compression pointers were followed for one name.
+    TooManyJumps,
+    /// A compression pointer doesn't point before the part of the name read so
+    /// far. Such pointers can form loops.
+    BadPointer { target: usize },
+    /// A label uses the reserved 0x40 or 0x80 length prefixes.
+    BadLabel { len: u8 },
+    /// The RDATA of a record doesn't match its RDLENGTH.
+    BadRdataLength { expected: u16, actual: usize },
+    /// Text input, such as a type mnemonic or generic RDATA, is malformed.
+    Syntax(String),
+}
+
+/// The part of a message in which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Section {
+    Header,
+    Question,
+    Answer,
+    Authority,
+    Additional,
+}
+
+/// An error while parsing or serializing a DNS message, along with the byte
+/// offset it happened at and, when known, the section and index of the
+/// question or record being processed. For text input the offset is the
+/// position in that text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DnsError {
+    pub kind: DnsErrorKind,
+    pub offset: usize,
+    pub location: Option<(Section, usize)>,
+}
+
+impl DnsError {
+    pub fn new(kind: DnsErrorKind, offset: usize) -> DnsError {
+        DnsError {
+            kind,
+            offset,
+            location: None,
+        }
+    }
+
+    pub fn syntax<S: Into<String>>(msg: S) -> DnsError {
+        DnsError::new(DnsErrorKind::Syntax(msg.into()), 0)
+    }
+
+    /// Records which question or record was being processed, unless a more
+    /// specific location was already recorded.
+    pub fn in_section(mut self, section: Section, index: usize) -> DnsError {
+        if self.location.is_none() {
+            self.location = Some((section, index));
+        }
+        self
+    }
+
+    /// Whether the error was caused by the input rather than by a limit on
+    /// our side, i.e. whether the sender should be told FORMERR.
+    pub fn is_malformed_input(&self) -> bool {
+        self.kind != DnsErrorKind::BufferFull
+    }
+}
+
+impl fmt::Display for DnsErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DnsErrorKind::EndOfBuffer => write!(f, "End of buffer"),
+            DnsErrorKind::BufferFull => write!(f, "Buffer full"),
+            DnsErrorKind::LabelTooLong => {
+                write!(f, "Single label exceeds 63 characters of length")
+            }
+            DnsErrorKind::NameTooLong => write!(f, "Name exceeds {} bytes", MAX_NAME_LEN),
+            DnsErrorKind::TooManyJumps => {
+                write!(f, "Name has more than {} compression pointers", MAX_JUMPS)
+            }
+            DnsErrorKind::BadPointer { target } => write!(
+                f,
+                "Compression pointer to {} doesn't point before the name",
+                target
+            ),
+            DnsErrorKind::BadLabel { len } => write!(f, "Invalid label length {:#04x}", len),
+            DnsErrorKind::BadRdataLength { expected, actual } => write!(
+                f,
+                "RDATA length {} doesn't match contents of {} bytes",
+                expected, actual
+            ),
+            DnsErrorKind::Syntax(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Section::Header => write!(f, "header"),
+            Section::Question => write!(f, "question"),
+            Section::Answer => write!(f, "answer"),
+            Section::Authority => write!(f, "authority"),
+            Section::Additional => write!(f, "additional"),
+        }
+    }
+}
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at offset {}", self.kind, self.offset)?;
+        match self.location {
+            Some((Section::Header, _)) => write!(f, " in header"),
+            Some((section, index)) => write!(f, " in {} {}", section, index),
+            None => Ok(()),
+        }
+    }
+}
+
+impl error::Error for DnsError {}
+
+/// Lets code doing I/O alongside parsing, such as the server, use `?` on
+/// codec results.
+impl From<DnsError> for io::Error {
+    fn from(err: DnsError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
@@ -1,6 +1,6 @@
-use std::io::Result;
-
-use super::byte_packet_buffer::BytePacketBuffer;
+use super::dns_error::Result;
+use super::opcode::Opcode;
+use super::packet_buffer::PacketBuffer;
 use super::result_code::ResultCode;
 
 #[derive(Clone, Debug)]
@@ -10,10 +10,10 @@ pub struct DnsHeader {
     pub recursion_desired: bool,    // 1 bit
     pub truncated_message: bool,    // 1 bit
     pub authoritative_answer: bool, // 1 bit
-    pub opcode: u8,                 // 4 bits
+    pub opcode: Opcode,             // 4 bits
     pub response: bool,             // 1 bit
 
-    pub rescode: ResultCode,       // 4 bits
+    pub rescode: ResultCode,       // 4 bits, see DnsPacket::rescode
     pub checking_disabled: bool,   // 1 bit
     pub authed_data: bool,         // 1 bit
     pub z: bool,                   // 1 bit
@@ -25,6 +25,12 @@ pub struct DnsHeader {
     pub resource_entries: u16,      // 16 bits
 }
 
+impl Default for DnsHeader {
+    fn default() -> Self {
+        DnsHeader::new()
+    }
+}
+
 impl DnsHeader {
     pub fn new() -> DnsHeader {
         DnsHeader {
@@ -33,7 +39,7 @@ impl DnsHeader {
             recursion_desired: false,
             truncated_message: false,
             authoritative_answer: false,
-            opcode: 0,
+            opcode: Opcode::QUERY,
             response: false,
 
             rescode: ResultCode::NOERROR,
@@ -49,7 +55,7 @@ impl DnsHeader {
         }
     }
 
-    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         self.id = buffer.read_u16()?;
 
         let flags = buffer.read_u16()?;
@@ -58,10 +64,10 @@ impl DnsHeader {
         self.recursion_desired = (a & (1 << 0)) > 0;
         self.truncated_message = (a & (1 << 1)) > 0;
         self.authoritative_answer = (a & (1 << 2)) > 0;
-        self.opcode = (a >> 3) & 0x0F;
+        self.opcode = Opcode::from_num(a >> 3);
         self.response = (a & (1 << 7)) > 0;
 
-        self.rescode = ResultCode::from_num(b & 0x0F);
+        self.rescode = ResultCode::from_num((b & 0x0F) as u16);
         self.checking_disabled = (b & (1 << 4)) > 0;
         self.authed_data = (b & (1 << 5)) > 0;
         self.z = (b & (1 << 6)) > 0;
@@ -76,19 +82,19 @@ impl DnsHeader {
         Ok(())
     }
 
-    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn write<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         buffer.write_u16(self.id)?;
 
         buffer.write_u8(
             (self.recursion_desired as u8)
                 | ((self.truncated_message as u8) << 1)
                 | ((self.authoritative_answer as u8) << 2)
-                | (self.opcode << 3)
-                | ((self.response as u8) << 7) as u8,
+                | ((self.opcode.to_num() & 0x0F) << 3)
+                | ((self.response as u8) << 7),
         )?;
 
         buffer.write_u8(
-            (self.rescode.clone() as u8)
+            ((self.rescode.to_num() & 0x0F) as u8)
                 | ((self.checking_disabled as u8) << 4)
                 | ((self.authed_data as u8) << 5)
                 | ((self.z as u8) << 6)
@@ -1,13 +1,18 @@
-use std::io::Result;
-
 extern crate rand;
 use rand::random;
+use std::net::Ipv4Addr;
 
-use super::byte_packet_buffer::BytePacketBuffer;
+use super::dns_error::{Result, Section};
 use super::dns_header::DnsHeader;
 use super::dns_question::DnsQuestion;
 use super::dns_record::DnsRecord;
+use super::edns::{EdnsOption, DEFAULT_EDNS_PAYLOAD_SIZE};
+use super::extended_error::ExtendedError;
+use super::name::Name;
+use super::packet_buffer::PacketBuffer;
+use super::query_class::QueryClass;
 use super::query_type::QueryType;
+use super::result_code::ResultCode;
 
 #[derive(Clone, Debug)]
 pub struct DnsPacket {
@@ -18,6 +23,12 @@ pub struct DnsPacket {
     pub resources: Vec<DnsRecord>,
 }
 
+impl Default for DnsPacket {
+    fn default() -> Self {
+        DnsPacket::new()
+    }
+}
+
 impl DnsPacket {
     pub fn new() -> DnsPacket {
         DnsPacket {
@@ -29,64 +40,259 @@ impl DnsPacket {
         }
     }
 
-    pub fn from_buffer(buffer: &mut BytePacketBuffer) -> Result<DnsPacket> {
+    /// Starts building a recursive query for `name`, with a random ID and a
+    /// single IN class question. The rest of the packet can be adjusted with
+    /// the `with_*` methods, e.g.
+    /// `DnsPacket::query(&name, QueryType::MX).with_edns(4096, false)`.
+    pub fn query(name: &Name, qtype: QueryType) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = random::<u16>();
+        packet.header.recursion_desired = true;
+        packet.questions.push(DnsQuestion::new(name.clone(), qtype));
+        packet
+    }
+
+    /// Starts building the response to `request`. The ID, opcode, RD and CD
+    /// flags and questions are copied over, so that the client can match the
+    /// response to its query.
+    pub fn response_to(request: &DnsPacket) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = request.header.id;
+        packet.header.opcode = request.header.opcode;
+        packet.header.recursion_desired = request.header.recursion_desired;
+        packet.header.checking_disabled = request.header.checking_disabled;
+        packet.header.response = true;
+        packet.questions = request.questions.clone();
+        packet
+    }
+
+    pub fn with_id(mut self, id: u16) -> DnsPacket {
+        self.header.id = id;
+        self
+    }
+
+    /// Sets the class of every question in the packet.
+    pub fn with_class(mut self, qclass: QueryClass) -> DnsPacket {
+        for question in &mut self.questions {
+            question.qclass = qclass;
+        }
+        self
+    }
+
+    pub fn with_recursion_desired(mut self, enabled: bool) -> DnsPacket {
+        self.header.recursion_desired = enabled;
+        self
+    }
+
+    pub fn with_recursion_available(mut self, enabled: bool) -> DnsPacket {
+        self.header.recursion_available = enabled;
+        self
+    }
+
+    pub fn with_checking_disabled(mut self, enabled: bool) -> DnsPacket {
+        self.header.checking_disabled = enabled;
+        self
+    }
+
+    /// Advertises EDNS0 support with the given UDP payload size, as with
+    /// `set_edns`.
+    pub fn with_edns(mut self, payload_size: u16, dnssec_ok: bool) -> DnsPacket {
+        self.set_edns(payload_size, dnssec_ok);
+        self
+    }
+
+    pub fn with_rescode(mut self, rescode: ResultCode) -> DnsPacket {
+        self.set_rescode(rescode);
+        self
+    }
+
+    pub fn from_buffer<T: PacketBuffer>(buffer: &mut T) -> Result<DnsPacket> {
         let mut result = DnsPacket::new();
-        result.header.read(buffer)?;
+        result
+            .header
+            .read(buffer)
+            .map_err(|e| e.in_section(Section::Header, 0))?;
 
-        for _ in 0..result.header.questions {
-            let mut question = DnsQuestion::new("".to_string(), QueryType::UNKNOWN(0));
-            question.read(buffer)?;
+        for i in 0..result.header.questions {
+            let mut question = DnsQuestion::new(Name::root(), QueryType::UNKNOWN(0));
+            question
+                .read(buffer)
+                .map_err(|e| e.in_section(Section::Question, i as usize))?;
             result.questions.push(question);
         }
 
-        for _ in 0..result.header.answers {
-            let rec = DnsRecord::read(buffer)?;
+        for i in 0..result.header.answers {
+            let rec =
+                DnsRecord::read(buffer).map_err(|e| e.in_section(Section::Answer, i as usize))?;
             result.answers.push(rec);
         }
 
-        for _ in 0..result.header.authoritative_entries {
-            let rec = DnsRecord::read(buffer)?;
+        for i in 0..result.header.authoritative_entries {
+            let rec = DnsRecord::read(buffer)
+                .map_err(|e| e.in_section(Section::Authority, i as usize))?;
             result.authorities.push(rec);
         }
 
-        for _ in 0..result.header.resource_entries {
-            let rec = DnsRecord::read(buffer)?;
+        for i in 0..result.header.resource_entries {
+            let rec = DnsRecord::read(buffer)
+                .map_err(|e| e.in_section(Section::Additional, i as usize))?;
             result.resources.push(rec);
         }
 
         Ok(result)
     }
 
-    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
+    pub fn write<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
         self.header.questions = self.questions.len() as u16;
         self.header.answers = self.answers.len() as u16;
         self.header.authoritative_entries = self.authorities.len() as u16;
         self.header.resource_entries = self.resources.len() as u16;
 
-        self.header.write(buffer)?;
+        self.header
+            .write(buffer)
+            .map_err(|e| e.in_section(Section::Header, 0))?;
 
-        for question in &mut self.questions {
-            question.write(buffer)?;
+        for (i, question) in self.questions.iter_mut().enumerate() {
+            question
+                .write(buffer)
+                .map_err(|e| e.in_section(Section::Question, i))?;
         }
-        for rec in &self.answers {
-            rec.write(buffer)?;
+        for (i, rec) in self.answers.iter().enumerate() {
+            rec.write(buffer)
+                .map_err(|e| e.in_section(Section::Answer, i))?;
         }
-        for rec in &self.authorities {
-            rec.write(buffer)?;
+        for (i, rec) in self.authorities.iter().enumerate() {
+            rec.write(buffer)
+                .map_err(|e| e.in_section(Section::Authority, i))?;
         }
-        for rec in &self.resources {
-            rec.write(buffer)?;
+        for (i, rec) in self.resources.iter().enumerate() {
+            rec.write(buffer)
+                .map_err(|e| e.in_section(Section::Additional, i))?;
         }
 
         Ok(())
     }
 
+    /// Returns the OPT pseudo-record from the additional section, if the
+    /// packet uses EDNS0.
+    fn get_opt(&self) -> Option<&DnsRecord> {
+        self.resources
+            .iter()
+            .find(|rec| matches!(rec, DnsRecord::OPT { .. }))
+    }
+
+    /// The full 12 bit response code, combining the 4 bits in the header with
+    /// the extended RCODE bits of the OPT record. Always use this rather than
+    /// `header.rescode`, which e.g. reads BADVERS as NOERROR.
+    pub fn rescode(&self) -> ResultCode {
+        let high = self.edns_extended_rcode().unwrap_or(0) as u16;
+        ResultCode::from_num((high << 4) | (self.header.rescode.to_num() & 0x0F))
+    }
+
+    /// Sets the response code, splitting it over the header and the OPT
+    /// record. An OPT record is added if the code doesn't fit in the header
+    /// alone. Only the lower 12 bits of `UNKNOWN` codes can be represented.
+    pub fn set_rescode(&mut self, rescode: ResultCode) {
+        let num = rescode.to_num();
+        self.header.rescode = ResultCode::from_num(num & 0x0F);
+
+        let high = ((num >> 4) & 0xFF) as u32;
+        if high != 0 && self.get_opt().is_none() {
+            self.set_edns(DEFAULT_EDNS_PAYLOAD_SIZE, false);
+        }
+        for rec in &mut self.resources {
+            if let DnsRecord::OPT { ref mut flags, .. } = *rec {
+                *flags = (*flags & 0x00FF_FFFF) | (high << 24);
+            }
+        }
+    }
+
+    /// The largest UDP payload the sender can receive, or `None` if the
+    /// packet doesn't use EDNS0.
+    pub fn edns_payload_size(&self) -> Option<u16> {
+        match self.get_opt() {
+            Some(DnsRecord::OPT { packet_len, .. }) => Some(*packet_len),
+            _ => None,
+        }
+    }
+
+    /// The upper 8 bits of the 12 bit extended RCODE.
+    pub fn edns_extended_rcode(&self) -> Option<u8> {
+        match self.get_opt() {
+            Some(DnsRecord::OPT { flags, .. }) => Some((flags >> 24) as u8),
+            _ => None,
+        }
+    }
+
+    pub fn edns_version(&self) -> Option<u8> {
+        match self.get_opt() {
+            Some(DnsRecord::OPT { flags, .. }) => Some(((flags >> 16) & 0xFF) as u8),
+            _ => None,
+        }
+    }
+
+    /// Whether the sender is able to accept DNSSEC records (the DO bit).
+    pub fn edns_dnssec_ok(&self) -> bool {
+        match self.get_opt() {
+            Some(DnsRecord::OPT { flags, .. }) => (flags & 0x8000) > 0,
+            _ => false,
+        }
+    }
+
+    pub fn edns_options(&self) -> &[EdnsOption] {
+        match self.get_opt() {
+            Some(DnsRecord::OPT { options, .. }) => options,
+            _ => &[],
+        }
+    }
+
+    /// The Extended DNS Errors (RFC 8914) attached to the packet.
+    pub fn extended_errors(&self) -> Vec<ExtendedError> {
+        self.edns_options()
+            .iter()
+            .filter_map(ExtendedError::from_option)
+            .collect()
+    }
+
+    /// Attaches an Extended DNS Error, adding an OPT record if the packet
+    /// doesn't have one yet.
+    pub fn add_extended_error(&mut self, error: ExtendedError) {
+        if self.get_opt().is_none() {
+            self.set_edns(DEFAULT_EDNS_PAYLOAD_SIZE, false);
+        }
+        for rec in &mut self.resources {
+            if let DnsRecord::OPT {
+                ref mut options, ..
+            } = *rec
+            {
+                options.push(error.to_option());
+                break;
+            }
+        }
+    }
+
+    /// Adds an OPT record advertising `payload_size` to the packet, replacing
+    /// any existing one.
+    pub fn set_edns(&mut self, payload_size: u16, dnssec_ok: bool) {
+        self.clear_edns();
+        self.resources.push(DnsRecord::OPT {
+            packet_len: payload_size,
+            flags: if dnssec_ok { 0x8000 } else { 0 },
+            options: Vec::new(),
+        });
+    }
+
+    pub fn clear_edns(&mut self) {
+        self.resources
+            .retain(|rec| !matches!(rec, DnsRecord::OPT { .. }));
+    }
+
     /// Allows us to pick a random A record from a packet.
     pub fn get_random_a(&self) -> Option<String> {
         if !self.answers.is_empty() {
             let idx = random::<usize>() % self.answers.len();
             let a_record = &self.answers[idx];
-            if let DnsRecord::A{ref addr, .. } = *a_record {
+            if let DnsRecord::A { ref addr, .. } = *a_record {
                 return Some(addr.to_string());
             }
         }
@@ -94,64 +300,300 @@ impl DnsPacket {
         None
     }
 
-    /// Returns actual IP for an NS record if possible.
-    pub fn get_resolved_ns(&self, qname: &str) -> Option<String> {
-        // first scan the list of NS records in the authorities section
-        let mut new_authorities = Vec::new();
-        for auth in &self.authorities {
-            if let DnsRecord::NS { ref domain, ref host, .. } = *auth {
-                if !qname.ends_with(domain) {
+    /// Orders the SRV answers the way RFC 2782 says clients should try them:
+    /// by ascending priority, and within each priority by repeated weighted
+    /// random selection. Returns the `(target, port)` pairs in that order,
+    /// leaving out targets of "." which mean the service isn't available.
+    pub fn get_srv_targets(&self) -> Vec<(Name, u16)> {
+        let mut records = Vec::new();
+        for rec in &self.answers {
+            if let DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                ref host,
+                ..
+            } = *rec
+            {
+                if host.is_root() {
                     continue;
                 }
+                records.push((priority, weight, host.clone(), port));
+            }
+        }
+        records.sort_by_key(|rec| rec.0);
+
+        let mut targets = Vec::new();
+        let mut start = 0;
+        while start < records.len() {
+            let priority = records[start].0;
+            let end = records[start..]
+                .iter()
+                .position(|rec| rec.0 != priority)
+                .map_or(records.len(), |n| start + n);
+
+            // Zero weight records go first so that they have a small chance of
+            // being picked, as the RFC recommends.
+            let mut group = records[start..end].to_vec();
+            group.sort_by_key(|rec| rec.1 != 0);
+
+            while !group.is_empty() {
+                let total: u32 = group.iter().map(|rec| rec.1 as u32).sum();
+                let pick = random::<u32>() % (total + 1);
 
-                // after finding an NS record, scan the resources record for a matching
-                // A record
-                for rsrc in &self.resources {
-                    if let DnsRecord::A { ref domain, ref addr, ttl } = *rsrc {
-                        if domain != host {
-                            continue;
-                        }
-
-                        let rec = DnsRecord::A {
-                            domain: host.clone(),
-                            addr: *addr,
-                            ttl: ttl
-                        };
-
-                        // push any matches to list
-                        new_authorities.push(rec);
+                let mut running = 0;
+                let mut idx = group.len() - 1;
+                for (i, rec) in group.iter().enumerate() {
+                    running += rec.1 as u32;
+                    if running >= pick {
+                        idx = i;
+                        break;
                     }
                 }
+
+                let (_, _, host, port) = group.remove(idx);
+                targets.push((host, port));
             }
+
+            start = end;
         }
 
-        // pick the first match
-        if !new_authorities.is_empty() {
-            if let DnsRecord::A { addr, .. } = new_authorities[0] {
-                return Some(addr.to_string());
+        targets
+    }
+
+    /// The addresses of all the A records among the answers.
+    pub fn get_a_addrs(&self) -> Vec<Ipv4Addr> {
+        self.answers
+            .iter()
+            .filter_map(|rec| match *rec {
+                DnsRecord::A { addr, .. } => Some(addr),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns actual IP for an NS record if possible.
+    pub fn get_resolved_ns(&self, qname: &Name) -> Option<String> {
+        self.get_resolved_nss(qname)
+            .first()
+            .map(|addr| addr.to_string())
+    }
+
+    /// The addresses of every nameserver in a referral for `qname` that
+    /// came with glue, i.e. whose A records are in the additional section.
+    pub fn get_resolved_nss(&self, qname: &Name) -> Vec<Ipv4Addr> {
+        let mut addrs = Vec::new();
+        for host in self.get_ns_hosts(qname) {
+            for rsrc in &self.resources {
+                if let DnsRecord::A {
+                    ref domain, addr, ..
+                } = *rsrc
+                {
+                    if domain == host && !addrs.contains(&addr) {
+                        addrs.push(addr);
+                    }
+                }
             }
         }
 
+        addrs
+    }
+
+    pub fn get_unresolved_ns(&self, qname: &Name) -> Option<Name> {
+        let hosts = self.get_ns_hosts(qname);
+        if !hosts.is_empty() {
+            let idx = random::<usize>() % hosts.len();
+            return Some(hosts[idx].clone());
+        }
+
         None
     }
 
-    pub fn get_unresolved_ns(&self, qname: &str) -> Option<String> {
-        let mut new_authorities = Vec::new();
-        for auth in &self.authorities {
-            if let DnsRecord::NS { ref domain, ref host, .. } = *auth {
-                if !qname.ends_with(domain) {
-                    continue;
-                }
+    /// The names of every nameserver in a referral for `qname`, whether or
+    /// not their addresses came along.
+    pub fn get_unresolved_nss(&self, qname: &Name) -> Vec<Name> {
+        self.get_ns_hosts(qname).into_iter().cloned().collect()
+    }
 
-                new_authorities.push(host);
-            }
+    fn get_ns_hosts(&self, qname: &Name) -> Vec<&Name> {
+        self.authorities
+            .iter()
+            .filter_map(|auth| match *auth {
+                DnsRecord::NS {
+                    ref domain,
+                    ref host,
+                    ..
+                } if qname.is_subdomain_of(domain) => Some(host),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::byte_packet_buffer::BytePacketBuffer;
+    use crate::dns::vector_packet_buffer::VectorPacketBuffer;
+
+    fn packet(question: &str, answers: &[&str]) -> DnsPacket {
+        let mut packet = DnsPacket::new();
+        packet.header.id = 1;
+        packet.questions.push(question.parse().unwrap());
+        for rec in answers {
+            packet.answers.push(rec.parse().unwrap());
         }
+        packet
+    }
+
+    fn write(packet: &mut DnsPacket, compress: bool) -> Vec<u8> {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.set_compression(compress);
+        packet.write(&mut buffer).unwrap();
+        buffer.buf
+    }
+
+    fn read(data: &[u8]) -> DnsPacket {
+        DnsPacket::from_buffer(&mut VectorPacketBuffer::from_bytes(data)).unwrap()
+    }
 
-        if !new_authorities.is_empty() {
-            let idx = random::<usize>() % new_authorities.len();
-            return Some(new_authorities[idx].clone());
+    fn contains(data: &[u8], part: &[u8]) -> bool {
+        data.windows(part.len()).any(|w| w == part)
+    }
+
+    #[test]
+    fn compresses_repeated_names() {
+        let mut packet = packet(
+            "www.example.com. IN CNAME",
+            &[
+                "www.example.com. 300 IN CNAME example.com.",
+                "example.com. 300 IN MX 10 mail.example.com.",
+            ],
+        );
+        let compressed = write(&mut packet, true);
+        let uncompressed = write(&mut packet, false);
+        assert!(compressed.len() < uncompressed.len());
+
+        // The question name starts right after the header, at 12, and
+        // `example.com.` within it at 16.
+        assert_eq!(&compressed[12..29], b"\x03www\x07example\x03com\x00");
+        assert_eq!(&compressed[33..35], b"\xc0\x0c");
+        assert!(contains(&compressed, b"\x04mail\xc0\x10"));
+
+        for data in &[compressed, uncompressed] {
+            let parsed = read(data);
+            assert_eq!(parsed.questions, packet.questions);
+            assert_eq!(parsed.answers, packet.answers);
         }
+    }
 
-        None
+    #[test]
+    fn compresses_names_of_the_same_case_only() {
+        let mut packet = packet(
+            "WWW.Example.COM. IN A",
+            &[
+                "www.example.com. 300 IN A 192.0.2.1",
+                "WWW.Example.COM. 300 IN A 192.0.2.2",
+            ],
+        );
+        let data = write(&mut packet, true);
+
+        // The first answer is written out in full, the second points to the
+        // question.
+        assert!(contains(&data, b"\x03www\x07example\x03com\x00"));
+        assert!(contains(&data, b"\xc0\x0c\x00\x01\x00\x01"));
+
+        let parsed = read(&data);
+        for (rec, sent) in parsed.answers.iter().zip(&packet.answers) {
+            assert!(rec.domain().eq_exact(sent.domain()));
+        }
+        assert!(parsed.questions[0].name.eq_exact(&packet.questions[0].name));
+    }
+
+    #[test]
+    fn does_not_compress_srv_targets() {
+        let mut packet = packet(
+            "_sip._tcp.example.com. IN SRV",
+            &["_sip._tcp.example.com. 300 IN SRV 10 60 5060 example.com."],
+        );
+        let data = write(&mut packet, true);
+        assert!(data.ends_with(b"\x13\xc4\x07example\x03com\x00"));
+        assert_eq!(read(&data).answers, packet.answers);
+    }
+
+    #[test]
+    fn compresses_in_byte_packet_buffers() {
+        let mut packet = packet(
+            "example.com. IN NS",
+            &[
+                "example.com. 300 IN NS ns1.example.com.",
+                "example.com. 300 IN NS ns2.example.com.",
+            ],
+        );
+        let mut buffer = BytePacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        let data = buffer.buf[..buffer.pos()].to_vec();
+        assert_eq!(data, write(&mut packet, true));
+
+        let mut buffer = BytePacketBuffer::new();
+        buffer.set_compression(false);
+        packet.write(&mut buffer).unwrap();
+        assert_eq!(&buffer.buf[..buffer.pos()], &write(&mut packet, false)[..]);
+    }
+
+    #[test]
+    fn orders_srv_targets_by_priority() {
+        let packet = packet(
+            "_sip._udp.example.com. IN SRV",
+            &[
+                "_sip._udp.example.com. 300 IN SRV 20 0 5060 backup.example.com.",
+                "_sip._udp.example.com. 300 IN SRV 10 60 5060 a.example.com.",
+                "_sip._udp.example.com. 300 IN SRV 10 0 5061 .",
+                "_sip._udp.example.com. 300 IN SRV 10 40 5060 b.example.com.",
+                "_sip._udp.example.com. 300 IN SRV 30 0 5060 .",
+                "_sip._udp.example.com. 300 IN A 192.0.2.1",
+            ],
+        );
+
+        for _ in 0..50 {
+            let targets = packet.get_srv_targets();
+            let hosts: Vec<String> = targets.iter().map(|t| t.0.to_string()).collect();
+            assert_eq!(hosts.len(), 3);
+            assert!(hosts[..2].contains(&"a.example.com.".to_string()));
+            assert!(hosts[..2].contains(&"b.example.com.".to_string()));
+            assert_eq!(hosts[2], "backup.example.com.");
+            assert!(targets.iter().all(|t| t.1 == 5060));
+        }
+    }
+
+    #[test]
+    fn keeps_srv_targets_of_zero_weight() {
+        let weightless = packet(
+            "_ldap._tcp.example.com. IN SRV",
+            &[
+                "_ldap._tcp.example.com. 300 IN SRV 0 0 389 a.example.com.",
+                "_ldap._tcp.example.com. 300 IN SRV 0 0 389 b.example.com.",
+                "_ldap._tcp.example.com. 300 IN SRV 0 0 389 c.example.com.",
+            ],
+        );
+
+        for _ in 0..50 {
+            let mut hosts: Vec<String> = weightless
+                .get_srv_targets()
+                .iter()
+                .map(|t| t.0.to_string())
+                .collect();
+            hosts.sort();
+            assert_eq!(
+                hosts,
+                vec!["a.example.com.", "b.example.com.", "c.example.com."]
+            );
+        }
+
+        let only_unavailable = packet(
+            "_ldap._tcp.example.com. IN SRV",
+            &["_ldap._tcp.example.com. 300 IN SRV 0 0 0 ."],
+        );
+        assert!(only_unavailable.get_srv_targets().is_empty());
     }
 }
@@ -0,0 +1,734 @@
+//! A borrowed view of a DNS message that is parsed lazily, for callers that
+//! only need to look at parts of a packet, such as logging or filtering.
+//! Nothing is allocated: names are decompressed on demand straight from the
+//! message bytes, and the sections are walked as they are iterated. Anything
+//! that needs the full record data can convert to the owned types.
+
+use std::fmt;
+use std::fmt::Write;
+
+use super::dns_error::{DnsError, DnsErrorKind, Result, Section};
+use super::dns_header::DnsHeader;
+use super::dns_packet::DnsPacket;
+use super::dns_question::DnsQuestion;
+use super::dns_record::DnsRecord;
+use super::name::{fmt_label, Name};
+use super::packet_buffer::{PacketBuffer, MAX_JUMPS, MAX_NAME_LEN};
+use super::query_class::QueryClass;
+use super::query_type::QueryType;
+
+/// The size of the fixed header at the start of every message.
+const HEADER_LEN: usize = 12;
+
+/// A read-only buffer over borrowed bytes, which lets the owned types parse
+/// directly from the slice. Writing fails with `BufferFull`.
+struct SliceBuffer<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> SliceBuffer<'a> {
+    fn new(data: &'a [u8], pos: usize) -> SliceBuffer<'a> {
+        SliceBuffer { data, pos }
+    }
+}
+
+impl PacketBuffer for SliceBuffer<'_> {
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.seek(self.pos.saturating_add(steps))
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos > self.data.len() {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<u8> {
+        let res = self.get(self.pos)?;
+        self.pos += 1;
+        Ok(res)
+    }
+
+    fn write(&mut self, _val: u8) -> Result<()> {
+        Err(DnsError::new(DnsErrorKind::BufferFull, self.pos))
+    }
+
+    fn set(&mut self, pos: usize, _val: u8) -> Result<()> {
+        Err(DnsError::new(DnsErrorKind::BufferFull, pos))
+    }
+
+    fn get(&mut self, pos: usize) -> Result<u8> {
+        match self.data.get(pos) {
+            Some(b) => Ok(*b),
+            None => Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos)),
+        }
+    }
+
+    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
+        match self.data.get(start..start.saturating_add(len)) {
+            Some(range) => Ok(range),
+            None => Err(DnsError::new(DnsErrorKind::EndOfBuffer, start)),
+        }
+    }
+}
+
+fn get_u16(data: &[u8], pos: usize) -> Result<u16> {
+    match data.get(pos..pos + 2) {
+        Some(b) => Ok(((b[0] as u16) << 8) | (b[1] as u16)),
+        None => Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos)),
+    }
+}
+
+fn get_u32(data: &[u8], pos: usize) -> Result<u32> {
+    Ok(((get_u16(data, pos)? as u32) << 16) | (get_u16(data, pos + 2)? as u32))
+}
+
+/// A domain name inside a message, possibly compressed. The name has been
+/// checked when the view was created, so its labels can be walked without
+/// further error handling.
+#[derive(Clone, Copy, Debug)]
+pub struct NameRef<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> NameRef<'a> {
+    /// Checks the name at `pos` the same way `PacketBuffer::read_qname` does,
+    /// and returns it along with the position right after it.
+    fn parse(data: &'a [u8], start: usize) -> Result<(NameRef<'a>, usize)> {
+        let mut pos = start;
+        let mut end = None;
+        let mut jumps = 0;
+        let mut segment_start = pos;
+        let mut name_len = 0;
+
+        loop {
+            let len = match data.get(pos) {
+                Some(len) => *len,
+                None => return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos)),
+            };
+
+            if (len & 0xC0) == 0xC0 {
+                if jumps == MAX_JUMPS {
+                    return Err(DnsError::new(DnsErrorKind::TooManyJumps, pos));
+                }
+
+                let offset = (get_u16(data, pos)? ^ 0xC000) as usize;
+                if offset >= segment_start {
+                    return Err(DnsError::new(
+                        DnsErrorKind::BadPointer { target: offset },
+                        pos,
+                    ));
+                }
+
+                if end.is_none() {
+                    end = Some(pos + 2);
+                }
+                pos = offset;
+                segment_start = offset;
+                jumps += 1;
+            } else if (len & 0xC0) != 0 {
+                return Err(DnsError::new(DnsErrorKind::BadLabel { len }, pos));
+            } else {
+                pos += 1;
+
+                name_len += len as usize + 1;
+                if name_len > MAX_NAME_LEN {
+                    return Err(DnsError::new(DnsErrorKind::NameTooLong, pos));
+                }
+
+                if len == 0 {
+                    break;
+                }
+
+                pos += len as usize;
+                if pos > data.len() {
+                    return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
+                }
+            }
+        }
+
+        let name = NameRef { data, pos: start };
+        Ok((name, end.unwrap_or(pos)))
+    }
+
+    /// Iterates over the raw labels of the name, leaving out the root label.
+    pub fn labels(&self) -> Labels<'a> {
+        Labels {
+            data: self.data,
+            pos: self.pos,
+        }
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels().next().is_none()
+    }
+
+    /// Copies the name into an owned `Name`.
+    pub fn to_name(&self) -> Name {
+        // The name was validated when the view was created, so this can't
+        // fail.
+        Name::from_labels(self.labels()).unwrap_or_default()
+    }
+
+    /// Compares the name to a dotted name such as `www.example.com`, ignoring
+    /// ASCII case and a trailing dot.
+    pub fn eq_ignore_case(&self, name: &str) -> bool {
+        let mut other = name.split('.').filter(|label| !label.is_empty());
+        for label in self.labels() {
+            match other.next() {
+                Some(x) if x.as_bytes().eq_ignore_ascii_case(label) => {}
+                _ => return false,
+            }
+        }
+        other.next().is_none()
+    }
+}
+
+impl PartialEq<Name> for NameRef<'_> {
+    fn eq(&self, other: &Name) -> bool {
+        let mut other = other.labels();
+        for label in self.labels() {
+            match other.next() {
+                Some(x) if x.eq_ignore_ascii_case(label) => {}
+                _ => return false,
+            }
+        }
+        other.next().is_none()
+    }
+}
+
+/// Formats the name the same way as `Name`.
+impl fmt::Display for NameRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return f.write_char('.');
+        }
+
+        for label in self.labels() {
+            fmt_label(f, label)?;
+            f.write_char('.')?;
+        }
+        Ok(())
+    }
+}
+
+/// The labels of a `NameRef`, following compression pointers as needed.
+pub struct Labels<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        loop {
+            let len = *self.data.get(self.pos)?;
+            if (len & 0xC0) == 0xC0 {
+                self.pos = (get_u16(self.data, self.pos).ok()? ^ 0xC000) as usize;
+                continue;
+            }
+            if len == 0 {
+                return None;
+            }
+
+            let start = self.pos + 1;
+            self.pos = start + len as usize;
+            return self.data.get(start..self.pos);
+        }
+    }
+}
+
+/// A question inside a message.
+#[derive(Clone, Copy, Debug)]
+pub struct QuestionRef<'a> {
+    pub name: NameRef<'a>,
+    pub qtype: QueryType,
+    pub qclass: QueryClass,
+}
+
+impl QuestionRef<'_> {
+    pub fn to_question(&self) -> DnsQuestion {
+        DnsQuestion::with_class(self.name.to_name(), self.qtype, self.qclass)
+    }
+}
+
+/// A resource record inside a message. The RDATA is left as raw bytes; use
+/// `to_record` to decode it.
+#[derive(Clone, Copy, Debug)]
+pub struct RecordRef<'a> {
+    pub name: NameRef<'a>,
+    pub qtype: QueryType,
+    /// For OPT records this holds the UDP payload size rather than a class.
+    pub class: QueryClass,
+    /// For OPT records this holds the extended RCODE, version and flags.
+    pub ttl: u32,
+    pub rdata: &'a [u8],
+
+    /// The whole message and the position of the record in it, which are
+    /// needed to decompress names in the RDATA.
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl RecordRef<'_> {
+    pub fn to_record(&self) -> Result<DnsRecord> {
+        DnsRecord::read(&mut SliceBuffer::new(self.data, self.pos))
+    }
+}
+
+/// Iterates over the questions of a message. Iteration stops after the
+/// first error.
+pub struct Questions<'a> {
+    data: &'a [u8],
+    pos: usize,
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for Questions<'a> {
+    type Item = Result<QuestionRef<'a>>;
+
+    fn next(&mut self) -> Option<Result<QuestionRef<'a>>> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let result = read_question(self.data, self.pos);
+        let result = match result {
+            Ok((question, end)) => {
+                self.pos = end;
+                self.index += 1;
+                Ok(question)
+            }
+            Err(e) => {
+                let e = e.in_section(Section::Question, self.index);
+                self.count = 0;
+                Err(e)
+            }
+        };
+
+        Some(result)
+    }
+}
+
+fn read_question(data: &[u8], pos: usize) -> Result<(QuestionRef<'_>, usize)> {
+    let (name, pos) = NameRef::parse(data, pos)?;
+    let qtype = QueryType::from_num(get_u16(data, pos)?);
+    let qclass = QueryClass::from_num(get_u16(data, pos + 2)?);
+
+    let question = QuestionRef {
+        name,
+        qtype,
+        qclass,
+    };
+    Ok((question, pos + 4))
+}
+
+/// Iterates over the records of one section of a message. Iteration stops
+/// after the first error, which includes errors in earlier sections that had
+/// to be skipped to find this one.
+pub struct Records<'a> {
+    data: &'a [u8],
+    pos: Result<usize>,
+    section: Section,
+    index: usize,
+    count: usize,
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<RecordRef<'a>>;
+
+    fn next(&mut self) -> Option<Result<RecordRef<'a>>> {
+        if self.index >= self.count {
+            return None;
+        }
+
+        let result = match self.pos {
+            Ok(pos) => {
+                read_record(self.data, pos).map_err(|e| e.in_section(self.section, self.index))
+            }
+            Err(ref e) => Err(e.clone()),
+        };
+        let result = match result {
+            Ok((record, end)) => {
+                self.pos = Ok(end);
+                self.index += 1;
+                Ok(record)
+            }
+            Err(e) => {
+                self.count = 0;
+                Err(e)
+            }
+        };
+
+        Some(result)
+    }
+}
+
+fn read_record(data: &[u8], start: usize) -> Result<(RecordRef<'_>, usize)> {
+    let (name, pos) = NameRef::parse(data, start)?;
+    let qtype = QueryType::from_num(get_u16(data, pos)?);
+    let class = QueryClass::from_num(get_u16(data, pos + 2)?);
+    let ttl = get_u32(data, pos + 4)?;
+    let data_len = get_u16(data, pos + 8)? as usize;
+
+    let rdata_start = pos + 10;
+    let rdata = match data.get(rdata_start..rdata_start + data_len) {
+        Some(rdata) => rdata,
+        None => return Err(DnsError::new(DnsErrorKind::EndOfBuffer, rdata_start)),
+    };
+
+    let record = RecordRef {
+        name,
+        qtype,
+        class,
+        ttl,
+        rdata,
+        data,
+        pos: start,
+    };
+    Ok((record, rdata_start + data_len))
+}
+
+/// A DNS message borrowed from a byte slice. Only the header is parsed up
+/// front; the sections are parsed as they are iterated.
+#[derive(Clone, Debug)]
+pub struct DnsPacketRef<'a> {
+    pub header: DnsHeader,
+    data: &'a [u8],
+}
+
+impl<'a> DnsPacketRef<'a> {
+    pub fn new(data: &'a [u8]) -> Result<DnsPacketRef<'a>> {
+        let mut header = DnsHeader::new();
+        header
+            .read(&mut SliceBuffer::new(data, 0))
+            .map_err(|e| e.in_section(Section::Header, 0))?;
+
+        Ok(DnsPacketRef { header, data })
+    }
+
+    /// The bytes of the message.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn questions(&self) -> Questions<'a> {
+        Questions {
+            data: self.data,
+            pos: HEADER_LEN,
+            index: 0,
+            count: self.header.questions as usize,
+        }
+    }
+
+    pub fn answers(&self) -> Records<'a> {
+        self.records(Section::Answer, self.header.answers)
+    }
+
+    pub fn authorities(&self) -> Records<'a> {
+        self.records(Section::Authority, self.header.authoritative_entries)
+    }
+
+    pub fn resources(&self) -> Records<'a> {
+        self.records(Section::Additional, self.header.resource_entries)
+    }
+
+    fn records(&self, section: Section, count: u16) -> Records<'a> {
+        Records {
+            data: self.data,
+            pos: self.section_start(section),
+            section,
+            index: 0,
+            count: count as usize,
+        }
+    }
+
+    /// Finds where a record section starts by walking the sections before it.
+    fn section_start(&self, section: Section) -> Result<usize> {
+        let mut questions = self.questions();
+        for question in questions.by_ref() {
+            question?;
+        }
+        let mut pos = questions.pos;
+
+        let before = [
+            (Section::Answer, self.header.answers),
+            (Section::Authority, self.header.authoritative_entries),
+        ];
+        for &(earlier, count) in before.iter() {
+            if earlier == section {
+                break;
+            }
+
+            let mut records = Records {
+                data: self.data,
+                pos: Ok(pos),
+                section: earlier,
+                index: 0,
+                count: count as usize,
+            };
+            for record in records.by_ref() {
+                record?;
+            }
+            pos = records.pos?;
+        }
+
+        Ok(pos)
+    }
+
+    /// Parses the whole message into the owned representation.
+    pub fn to_packet(&self) -> Result<DnsPacket> {
+        DnsPacket::from_buffer(&mut SliceBuffer::new(self.data, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::vector_packet_buffer::VectorPacketBuffer;
+
+    fn parse(data: &[u8], pos: usize) -> Result<(String, usize)> {
+        let (name, end) = NameRef::parse(data, pos)?;
+        Ok((name.to_string(), end))
+    }
+
+    fn kind_at(data: &[u8], pos: usize) -> DnsErrorKind {
+        parse(data, pos).unwrap_err().kind
+    }
+
+    /// A name made of labels of the given lengths, in wire format.
+    fn labels(lens: &[usize]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &len in lens {
+            data.push(len as u8);
+            data.resize(data.len() + len, b'a');
+        }
+        data
+    }
+
+    /// The root name at 0 followed by `count` pointers, each pointing to the
+    /// one before it.
+    fn pointer_chain(count: usize) -> Vec<u8> {
+        let mut data = vec![0];
+        let mut prev = 0;
+        for _ in 0..count {
+            let pos = data.len();
+            data.push(0xc0);
+            data.push(prev as u8);
+            prev = pos;
+        }
+        data
+    }
+
+    #[test]
+    fn parses_plain_and_compressed_names() {
+        let data = b"\x03www\x07example\x03com\x00\x04MAIL\xc0\x04";
+        assert_eq!(
+            parse(data, 0).unwrap(),
+            ("www.example.com.".to_string(), 17)
+        );
+        assert_eq!(
+            parse(data, 17).unwrap(),
+            ("MAIL.example.com.".to_string(), 24)
+        );
+
+        let (name, _) = NameRef::parse(data, 17).unwrap();
+        assert!(name.eq_ignore_case("mail.EXAMPLE.com."));
+        assert!(!name.eq_ignore_case("mail.example"));
+        assert!(name == "mail.example.com".parse::<Name>().unwrap());
+        assert!(name
+            .to_name()
+            .eq_exact(&"MAIL.example.com".parse().unwrap()));
+    }
+
+    #[test]
+    fn rejects_the_same_hostile_names_as_read_qname() {
+        let mut too_long = labels(&[63, 63, 63, 62]);
+        too_long.push(0);
+        let mut too_long_through_pointer = labels(&[63, 63]);
+        too_long_through_pointer.push(0);
+        too_long_through_pointer.extend(labels(&[63, 63]));
+        too_long_through_pointer.extend(&[0xc0, 0x00]);
+        let chain = pointer_chain(MAX_JUMPS + 1);
+
+        let cases: Vec<(&[u8], usize, DnsErrorKind)> = vec![
+            (b"\xc0\x00", 0, DnsErrorKind::BadPointer { target: 0 }),
+            (
+                b"\x01a\xc0\x04\xc0\x00",
+                4,
+                DnsErrorKind::BadPointer { target: 4 },
+            ),
+            (
+                b"\xc0\x02\x01a\x00",
+                0,
+                DnsErrorKind::BadPointer { target: 2 },
+            ),
+            (
+                b"\x01a\x01b\xc0\x02",
+                0,
+                DnsErrorKind::BadPointer { target: 2 },
+            ),
+            (&chain, chain.len() - 2, DnsErrorKind::TooManyJumps),
+            (&too_long, 0, DnsErrorKind::NameTooLong),
+            (&too_long_through_pointer, 129, DnsErrorKind::NameTooLong),
+            (b"\x40a\x00", 0, DnsErrorKind::BadLabel { len: 0x40 }),
+            (b"\x80a\x00", 0, DnsErrorKind::BadLabel { len: 0x80 }),
+            (b"\x03ww", 0, DnsErrorKind::EndOfBuffer),
+            (b"\x00\xc0", 1, DnsErrorKind::EndOfBuffer),
+        ];
+        for (data, pos, kind) in cases {
+            assert_eq!(kind_at(data, pos), kind, "{:?} at {}", data, pos);
+
+            let mut buffer = VectorPacketBuffer::from_bytes(data);
+            buffer.seek(pos).unwrap();
+            assert_eq!(buffer.read_qname().unwrap_err().kind, kind);
+        }
+    }
+
+    #[test]
+    fn follows_up_to_max_jumps() {
+        let data = pointer_chain(MAX_JUMPS);
+        assert_eq!(
+            parse(&data, data.len() - 2).unwrap(),
+            (".".to_string(), data.len())
+        );
+    }
+
+    #[test]
+    fn accepts_names_of_max_len() {
+        let mut data = labels(&[63, 63, 63, 61]);
+        data.push(0);
+        let (name, end) = NameRef::parse(&data, 0).unwrap();
+        assert_eq!(end, MAX_NAME_LEN);
+        assert_eq!(name.labels().count(), 4);
+    }
+
+    /// A response with compressed names in every section.
+    fn compressed_packet() -> (DnsPacket, Vec<u8>) {
+        let mut packet = DnsPacket::new().with_id(7);
+        packet.header.response = true;
+        packet
+            .questions
+            .push("www.example.com. IN A".parse().unwrap());
+        for rec in &[
+            "www.example.com. 300 IN CNAME web.example.com.",
+            "web.example.com. 300 IN A 192.0.2.1",
+        ] {
+            packet.answers.push(rec.parse().unwrap());
+        }
+        packet
+            .authorities
+            .push("example.com. 300 IN NS ns1.example.com.".parse().unwrap());
+        packet
+            .resources
+            .push("ns1.example.com. 300 IN A 192.0.2.53".parse().unwrap());
+        packet.set_edns(1232, false);
+
+        let mut buffer = VectorPacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        (packet, buffer.buf)
+    }
+
+    #[test]
+    fn iterates_over_sections() {
+        let (packet, data) = compressed_packet();
+        let view = DnsPacketRef::new(&data).unwrap();
+        assert_eq!(view.header.id, 7);
+
+        let questions: Vec<_> = view.questions().map(|q| q.unwrap()).collect();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].to_question(), packet.questions[0]);
+
+        let answers: Vec<_> = view.answers().map(|r| r.unwrap()).collect();
+        assert_eq!(answers.len(), 2);
+        assert_eq!(answers[0].name.to_string(), "www.example.com.");
+        assert_eq!(answers[0].qtype, QueryType::CNAME);
+        assert_eq!(answers[1].qtype, QueryType::A);
+        assert_eq!(answers[1].rdata, &[192, 0, 2, 1]);
+
+        let authorities: Vec<_> = view.authorities().map(|r| r.unwrap()).collect();
+        assert_eq!(authorities.len(), 1);
+        assert_eq!(authorities[0].ttl, 300);
+
+        let resources: Vec<_> = view.resources().map(|r| r.unwrap()).collect();
+        assert_eq!(resources.len(), 2);
+        assert_eq!(resources[1].qtype, QueryType::OPT);
+        assert_eq!(resources[1].class.to_num(), 1232);
+    }
+
+    #[test]
+    fn converts_to_owned_types() {
+        let (packet, data) = compressed_packet();
+        let view = DnsPacketRef::new(&data).unwrap();
+
+        // The CNAME target is compressed, so decoding it needs the message.
+        let records: Vec<_> = view
+            .answers()
+            .map(|r| r.unwrap().to_record().unwrap())
+            .collect();
+        assert_eq!(records, packet.answers);
+
+        let owned = view.to_packet().unwrap();
+        assert_eq!(owned.questions, packet.questions);
+        assert_eq!(owned.answers, packet.answers);
+        assert_eq!(owned.authorities, packet.authorities);
+        assert_eq!(owned.resources, packet.resources);
+    }
+
+    #[test]
+    fn stops_iterating_at_the_first_error() {
+        let (_, mut data) = compressed_packet();
+        // Claim one more answer than there is room for before the end.
+        data.truncate(data.len() - 5);
+        let view = DnsPacketRef::new(&data).unwrap();
+
+        assert_eq!(view.answers().filter(|r| r.is_ok()).count(), 2);
+        let results: Vec<_> = view.resources().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        let err = results[1].as_ref().unwrap_err();
+        assert_eq!(err.kind, DnsErrorKind::EndOfBuffer);
+        assert_eq!(err.location, Some((Section::Additional, 1)));
+        assert!(view.to_packet().is_err());
+
+        // Errors in earlier sections carry over to the later ones.
+        data[12] = 0xc0;
+        let view = DnsPacketRef::new(&data).unwrap();
+        assert!(view.questions().next().unwrap().is_err());
+        let err = view.answers().next().unwrap().unwrap_err();
+        assert_eq!(err.location, Some((Section::Question, 0)));
+        assert_eq!(view.answers().count(), 1);
+    }
+
+    #[test]
+    fn rejects_short_headers() {
+        let err = DnsPacketRef::new(&[0; 11]).unwrap_err();
+        assert_eq!(err.kind, DnsErrorKind::EndOfBuffer);
+        assert_eq!(err.location, Some((Section::Header, 0)));
+    }
+
+    #[test]
+    fn slice_buffer_stops_at_the_end() {
+        let mut buffer = SliceBuffer::new(&[1, 2, 3], 0);
+        assert_eq!(
+            buffer.step(usize::MAX).unwrap_err().kind,
+            DnsErrorKind::EndOfBuffer
+        );
+        assert_eq!(
+            buffer.get_range(1, usize::MAX).unwrap_err().kind,
+            DnsErrorKind::EndOfBuffer
+        );
+        assert_eq!(buffer.get_range(1, 2).unwrap(), &[2, 3]);
+        assert_eq!(buffer.write(0).unwrap_err().kind, DnsErrorKind::BufferFull);
+    }
+}
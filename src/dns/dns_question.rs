@@ -1,36 +1,45 @@
-use std::io::Result;
-
-use super::byte_packet_buffer::BytePacketBuffer;
+use super::dns_error::Result;
+use super::name::Name;
+use super::packet_buffer::PacketBuffer;
+use super::query_class::QueryClass;
 use super::query_type::QueryType;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DnsQuestion {
-    pub name: String,
+    pub name: Name,
     pub qtype: QueryType,
+    pub qclass: QueryClass,
 }
 
 impl DnsQuestion {
-    pub fn new(name: String, qtype: QueryType) -> DnsQuestion {
+    /// Creates a question for the Internet (IN) class, which is what nearly
+    /// every query uses.
+    pub fn new(name: Name, qtype: QueryType) -> DnsQuestion {
+        DnsQuestion::with_class(name, qtype, QueryClass::IN)
+    }
+
+    pub fn with_class(name: Name, qtype: QueryType, qclass: QueryClass) -> DnsQuestion {
         DnsQuestion {
-            name: name,
-            qtype: qtype,
+            name,
+            qtype,
+            qclass,
         }
     }
 
-    pub fn read(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
-        buffer.read_qname(&mut self.name)?;
+    pub fn read<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
+        self.name = buffer.read_qname()?;
         self.qtype = QueryType::from_num(buffer.read_u16()?);
-        let _ = buffer.read_u16()?;
+        self.qclass = QueryClass::from_num(buffer.read_u16()?);
 
         Ok(())
     }
 
-    pub fn write(&mut self, buffer: &mut BytePacketBuffer) -> Result<()> {
-        buffer.write_qname(&mut self.name)?;
+    pub fn write<T: PacketBuffer>(&mut self, buffer: &mut T) -> Result<()> {
+        buffer.write_qname(&self.name)?;
 
         let typenum = self.qtype.to_num();
         buffer.write_u16(typenum)?;
-        buffer.write_u16(1)?;
+        buffer.write_u16(self.qclass.to_num())?;
 
         Ok(())
     }
@@ -1,58 +1,119 @@
-use std::io::Result;
 use std::net::{Ipv4Addr, Ipv6Addr};
 
-use super::byte_packet_buffer::BytePacketBuffer;
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::edns::EdnsOption;
+use super::name::Name;
+use super::packet_buffer::PacketBuffer;
+use super::query_class::QueryClass;
 use super::query_type::QueryType;
+use super::vector_packet_buffer::VectorPacketBuffer;
+
+/// The owner of every OPT record.
+static ROOT: Name = Name::root();
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[allow(dead_code)]
 pub enum DnsRecord {
+    /// A record of a type we don't model. The class and RDATA are kept
+    /// verbatim so that the record can be passed on unchanged (RFC 3597).
     UNKNOWN {
-        domain: String,
+        domain: Name,
         qtype: u16,
-        data_len: u16,
+        data: Vec<u8>,
+        class: QueryClass,
         ttl: u32,
     }, // 0
     A {
-        domain: String,
+        domain: Name,
         addr: Ipv4Addr,
+        class: QueryClass,
         ttl: u32,
     }, // 1
     NS {
-        domain: String,
-        host: String,
+        domain: Name,
+        host: Name,
+        class: QueryClass,
         ttl: u32,
     }, // 2
     CNAME {
-        domain: String,
-        host: String,
+        domain: Name,
+        host: Name,
+        class: QueryClass,
         ttl: u32,
     }, // 5
+    SOA {
+        domain: Name,
+        m_name: Name,
+        r_name: Name,
+        serial: u32,
+        refresh: u32,
+        retry: u32,
+        expire: u32,
+        minimum: u32,
+        class: QueryClass,
+        ttl: u32,
+    }, // 6
+    PTR {
+        domain: Name,
+        host: Name,
+        class: QueryClass,
+        ttl: u32,
+    }, // 12
     MX {
-        domain: String,
+        domain: Name,
         priority: u16,
-        host: String,
+        host: Name,
+        class: QueryClass,
         ttl: u32,
     }, // 15
+    /// Each entry is one character-string, kept as raw bytes since TXT data
+    /// isn't required to be valid UTF-8. Entries longer than 255 bytes are
+    /// written as several character-strings, so they read back as several
+    /// entries.
+    TXT {
+        domain: Name,
+        data: Vec<Vec<u8>>,
+        class: QueryClass,
+        ttl: u32,
+    }, // 16
     AAAA {
-        domain: String,
+        domain: Name,
         addr: Ipv6Addr,
+        class: QueryClass,
         ttl: u32,
     }, // 28
+    SRV {
+        domain: Name,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        host: Name,
+        class: QueryClass,
+        ttl: u32,
+    }, // 33
+    /// The EDNS0 pseudo-record. It is always owned by the root domain and
+    /// reuses the CLASS field for the UDP payload size and the TTL field for
+    /// the extended RCODE, version and DO flag.
+    OPT {
+        packet_len: u16,
+        flags: u32,
+        options: Vec<EdnsOption>,
+    }, // 41
 }
 
 impl DnsRecord {
-    pub fn read(buffer: &mut BytePacketBuffer) -> Result<DnsRecord> {
-        let mut domain = String::new();
-        buffer.read_qname(&mut domain)?;
+    pub fn read<T: PacketBuffer>(buffer: &mut T) -> Result<DnsRecord> {
+        let domain = buffer.read_qname()?;
 
         let qtype_num = buffer.read_u16()?;
         let qtype = QueryType::from_num(qtype_num);
-        let _ = buffer.read_u16()?;
+        let class_num = buffer.read_u16()?;
+        let class = QueryClass::from_num(class_num);
         let ttl = buffer.read_u32()?;
         let data_len = buffer.read_u16()?;
+        let data_end = buffer.pos() + data_len as usize;
 
-        match qtype {
+        let record: Result<DnsRecord> = match qtype {
             // Handle each record type separately, starting with the A record
             // type which remains the same as before.
             QueryType::A => {
@@ -61,13 +122,14 @@ impl DnsRecord {
                     ((raw_addr >> 24) & 0xFF) as u8,
                     ((raw_addr >> 16) & 0xFF) as u8,
                     ((raw_addr >> 8) & 0xFF) as u8,
-                    ((raw_addr >> 0) & 0xFF) as u8,
+                    (raw_addr & 0xFF) as u8,
                 );
 
                 Ok(DnsRecord::A {
-                    domain: domain,
-                    addr: addr,
-                    ttl: ttl,
+                    domain,
+                    addr,
+                    class,
+                    ttl,
                 })
             }
 
@@ -80,85 +142,192 @@ impl DnsRecord {
                 let raw_addr4 = buffer.read_u32()?;
                 let addr = Ipv6Addr::new(
                     ((raw_addr1 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr1 >> 0) & 0xFFFF) as u16,
+                    (raw_addr1 & 0xFFFF) as u16,
                     ((raw_addr2 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr2 >> 0) & 0xFFFF) as u16,
+                    (raw_addr2 & 0xFFFF) as u16,
                     ((raw_addr3 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr3 >> 0) & 0xFFFF) as u16,
+                    (raw_addr3 & 0xFFFF) as u16,
                     ((raw_addr4 >> 16) & 0xFFFF) as u16,
-                    ((raw_addr4 >> 0) & 0xFFFF) as u16,
+                    (raw_addr4 & 0xFFFF) as u16,
                 );
 
                 Ok(DnsRecord::AAAA {
-                    domain: domain,
-                    addr: addr,
-                    ttl: ttl,
+                    domain,
+                    addr,
+                    class,
+                    ttl,
                 })
             }
 
             // NS and CNAME both have the same structure.
             QueryType::NS => {
-                let mut ns = String::new();
-                buffer.read_qname(&mut ns)?;
+                let ns = buffer.read_qname()?;
 
                 Ok(DnsRecord::NS {
-                    domain: domain,
+                    domain,
                     host: ns,
-                    ttl: ttl,
+                    class,
+                    ttl,
                 })
             }
 
             QueryType::CNAME => {
-                let mut cname = String::new();
-                buffer.read_qname(&mut cname)?;
+                let cname = buffer.read_qname()?;
 
                 Ok(DnsRecord::CNAME {
-                    domain: domain,
+                    domain,
                     host: cname,
-                    ttl: ttl,
+                    class,
+                    ttl,
+                })
+            }
+
+            QueryType::PTR => {
+                let ptr = buffer.read_qname()?;
+
+                Ok(DnsRecord::PTR {
+                    domain,
+                    host: ptr,
+                    class,
+                    ttl,
+                })
+            }
+
+            // SOA starts with two names, the primary name server and the
+            // mailbox of the person responsible, followed by five counters.
+            QueryType::SOA => {
+                let m_name = buffer.read_qname()?;
+                let r_name = buffer.read_qname()?;
+
+                let serial = buffer.read_u32()?;
+                let refresh = buffer.read_u32()?;
+                let retry = buffer.read_u32()?;
+                let expire = buffer.read_u32()?;
+                let minimum = buffer.read_u32()?;
+
+                Ok(DnsRecord::SOA {
+                    domain,
+                    m_name,
+                    r_name,
+                    serial,
+                    refresh,
+                    retry,
+                    expire,
+                    minimum,
+                    class,
+                    ttl,
                 })
             }
 
             // MX is almost like the previous two, but with one extra field for priority.
             QueryType::MX => {
                 let priority = buffer.read_u16()?;
-                let mut mx = String::new();
-                buffer.read_qname(&mut mx)?;
+                let mx = buffer.read_qname()?;
 
                 Ok(DnsRecord::MX {
-                    domain: domain,
-                    priority: priority,
+                    domain,
+                    priority,
                     host: mx,
-                    ttl: ttl,
+                    class,
+                    ttl,
                 })
             }
 
-            // And we end with some code for handling unknown record types, as before.
+            // SRV is like MX with a weight and port added.
+            QueryType::SRV => {
+                let priority = buffer.read_u16()?;
+                let weight = buffer.read_u16()?;
+                let port = buffer.read_u16()?;
+                let srv = buffer.read_qname()?;
+
+                Ok(DnsRecord::SRV {
+                    domain,
+                    priority,
+                    weight,
+                    port,
+                    host: srv,
+                    class,
+                    ttl,
+                })
+            }
+
+            // TXT RDATA is a sequence of length-prefixed character-strings
+            // filling up the whole record.
+            QueryType::TXT => {
+                let end = buffer.pos() + data_len as usize;
+
+                let mut data = Vec::new();
+                while buffer.pos() < end {
+                    let len = buffer.read()? as usize;
+                    data.push(buffer.get_range(buffer.pos(), len)?.to_vec());
+                    buffer.step(len)?;
+                }
+
+                Ok(DnsRecord::TXT {
+                    domain,
+                    data,
+                    class,
+                    ttl,
+                })
+            }
+
+            // For OPT the class holds the requestor's UDP payload size and the
+            // TTL holds the extended RCODE and flags.
+            QueryType::OPT => {
+                let options = EdnsOption::read_all(buffer, data_len)?;
+
+                Ok(DnsRecord::OPT {
+                    packet_len: class_num,
+                    flags: ttl,
+                    options,
+                })
+            }
+
+            // And we end with some code for handling unknown record types,
+            // keeping the raw RDATA around so that it can be written back.
             QueryType::UNKNOWN(_) => {
+                let data = buffer.get_range(buffer.pos(), data_len as usize)?.to_vec();
                 buffer.step(data_len as usize)?;
 
                 Ok(DnsRecord::UNKNOWN {
-                    domain: domain,
+                    domain,
                     qtype: qtype_num,
-                    data_len: data_len,
-                    ttl: ttl,
+                    data,
+                    class,
+                    ttl,
                 })
             }
+        };
+        let record = record?;
+
+        // The RDATA has to be consumed exactly, otherwise the rest of the
+        // packet would be parsed from the wrong offset.
+        if buffer.pos() != data_end {
+            return Err(DnsError::new(
+                DnsErrorKind::BadRdataLength {
+                    expected: data_len,
+                    actual: buffer.pos() - (data_end - data_len as usize),
+                },
+                data_end - data_len as usize,
+            ));
         }
+
+        Ok(record)
     }
 
-    pub fn write(&self, buffer: &mut BytePacketBuffer) -> Result<usize> {
+    pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<usize> {
         let start_pos = buffer.pos();
 
         match *self {
             DnsRecord::A {
                 ref domain,
                 ref addr,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::A.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(4)?;
 
@@ -171,11 +340,12 @@ impl DnsRecord {
             DnsRecord::NS {
                 ref domain,
                 ref host,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::NS.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -189,11 +359,12 @@ impl DnsRecord {
             DnsRecord::CNAME {
                 ref domain,
                 ref host,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::CNAME.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -204,15 +375,66 @@ impl DnsRecord {
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
+            DnsRecord::PTR {
+                ref domain,
+                ref host,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::PTR.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_qname(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::SOA {
+                ref domain,
+                ref m_name,
+                ref r_name,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SOA.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_qname(m_name)?;
+                buffer.write_qname(r_name)?;
+                buffer.write_u32(serial)?;
+                buffer.write_u32(refresh)?;
+                buffer.write_u32(retry)?;
+                buffer.write_u32(expire)?;
+                buffer.write_u32(minimum)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
             DnsRecord::MX {
                 ref domain,
                 priority,
                 ref host,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::MX.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
 
                 let pos = buffer.pos();
@@ -224,14 +446,50 @@ impl DnsRecord {
                 let size = buffer.pos() - (pos + 2);
                 buffer.set_u16(pos, size as u16)?;
             }
+            DnsRecord::TXT {
+                ref domain,
+                ref data,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::TXT.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                // A character-string holds at most 255 bytes, so longer
+                // strings are split over several consecutive ones. The RDATA
+                // must hold at least one string, even if it's empty.
+                if data.is_empty() {
+                    buffer.write_u8(0)?;
+                }
+                for txt in data {
+                    if txt.is_empty() {
+                        buffer.write_u8(0)?;
+                    }
+                    for chunk in txt.chunks(255) {
+                        buffer.write_u8(chunk.len() as u8)?;
+                        for b in chunk {
+                            buffer.write_u8(*b)?;
+                        }
+                    }
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
             DnsRecord::AAAA {
                 ref domain,
                 ref addr,
+                class,
                 ttl,
             } => {
                 buffer.write_qname(domain)?;
                 buffer.write_u16(QueryType::AAAA.to_num())?;
-                buffer.write_u16(1)?;
+                buffer.write_u16(class.to_num())?;
                 buffer.write_u32(ttl)?;
                 buffer.write_u16(16)?;
 
@@ -239,11 +497,340 @@ impl DnsRecord {
                     buffer.write_u16(*octet)?;
                 }
             }
-            DnsRecord::UNKNOWN { .. } => {
-                println!("Skipping record: {:#?}", self);
+            DnsRecord::SRV {
+                ref domain,
+                priority,
+                weight,
+                port,
+                ref host,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(QueryType::SRV.to_num())?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                buffer.write_u16(priority)?;
+                buffer.write_u16(weight)?;
+                buffer.write_u16(port)?;
+
+                // RFC 2782 forbids compressing the target.
+                buffer.write_qname_uncompressed(host)?;
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::OPT {
+                packet_len,
+                flags,
+                ref options,
+            } => {
+                buffer.write_qname(&Name::root())?;
+                buffer.write_u16(QueryType::OPT.to_num())?;
+                buffer.write_u16(packet_len)?;
+                buffer.write_u32(flags)?;
+
+                let pos = buffer.pos();
+                buffer.write_u16(0)?;
+
+                for option in options {
+                    option.write(buffer)?;
+                }
+
+                let size = buffer.pos() - (pos + 2);
+                buffer.set_u16(pos, size as u16)?;
+            }
+            DnsRecord::UNKNOWN {
+                ref domain,
+                qtype,
+                ref data,
+                class,
+                ttl,
+            } => {
+                buffer.write_qname(domain)?;
+                buffer.write_u16(qtype)?;
+                buffer.write_u16(class.to_num())?;
+                buffer.write_u32(ttl)?;
+                buffer.write_u16(data.len() as u16)?;
+
+                for b in data {
+                    buffer.write_u8(*b)?;
+                }
             }
         }
 
         Ok(buffer.pos() - start_pos)
     }
+
+    /// The owner name of the record. OPT is always owned by the root.
+    pub fn domain(&self) -> &Name {
+        match *self {
+            DnsRecord::UNKNOWN { ref domain, .. }
+            | DnsRecord::A { ref domain, .. }
+            | DnsRecord::NS { ref domain, .. }
+            | DnsRecord::CNAME { ref domain, .. }
+            | DnsRecord::SOA { ref domain, .. }
+            | DnsRecord::PTR { ref domain, .. }
+            | DnsRecord::MX { ref domain, .. }
+            | DnsRecord::TXT { ref domain, .. }
+            | DnsRecord::AAAA { ref domain, .. }
+            | DnsRecord::SRV { ref domain, .. } => domain,
+            DnsRecord::OPT { .. } => &ROOT,
+        }
+    }
+
+    pub fn qtype(&self) -> QueryType {
+        match *self {
+            DnsRecord::UNKNOWN { qtype, .. } => QueryType::UNKNOWN(qtype),
+            DnsRecord::A { .. } => QueryType::A,
+            DnsRecord::NS { .. } => QueryType::NS,
+            DnsRecord::CNAME { .. } => QueryType::CNAME,
+            DnsRecord::SOA { .. } => QueryType::SOA,
+            DnsRecord::PTR { .. } => QueryType::PTR,
+            DnsRecord::MX { .. } => QueryType::MX,
+            DnsRecord::TXT { .. } => QueryType::TXT,
+            DnsRecord::AAAA { .. } => QueryType::AAAA,
+            DnsRecord::SRV { .. } => QueryType::SRV,
+            DnsRecord::OPT { .. } => QueryType::OPT,
+        }
+    }
+
+    /// The value of the record's CLASS field, which for OPT is the UDP
+    /// payload size.
+    pub fn class(&self) -> QueryClass {
+        match *self {
+            DnsRecord::UNKNOWN { class, .. }
+            | DnsRecord::A { class, .. }
+            | DnsRecord::NS { class, .. }
+            | DnsRecord::CNAME { class, .. }
+            | DnsRecord::SOA { class, .. }
+            | DnsRecord::PTR { class, .. }
+            | DnsRecord::MX { class, .. }
+            | DnsRecord::TXT { class, .. }
+            | DnsRecord::AAAA { class, .. }
+            | DnsRecord::SRV { class, .. } => class,
+            DnsRecord::OPT { packet_len, .. } => QueryClass::UNKNOWN(packet_len),
+        }
+    }
+
+    /// The value of the record's TTL field, which for OPT holds the extended
+    /// RCODE, version and flags.
+    pub fn ttl(&self) -> u32 {
+        match *self {
+            DnsRecord::UNKNOWN { ttl, .. }
+            | DnsRecord::A { ttl, .. }
+            | DnsRecord::NS { ttl, .. }
+            | DnsRecord::CNAME { ttl, .. }
+            | DnsRecord::SOA { ttl, .. }
+            | DnsRecord::PTR { ttl, .. }
+            | DnsRecord::MX { ttl, .. }
+            | DnsRecord::TXT { ttl, .. }
+            | DnsRecord::AAAA { ttl, .. }
+            | DnsRecord::SRV { ttl, .. } => ttl,
+            DnsRecord::OPT { flags, .. } => flags,
+        }
+    }
+
+    /// Changes the TTL, e.g. to count down the time a record has been cached.
+    /// OPT records are left alone since their TTL field holds flags.
+    pub fn set_ttl(&mut self, new_ttl: u32) {
+        match *self {
+            DnsRecord::UNKNOWN { ref mut ttl, .. }
+            | DnsRecord::A { ref mut ttl, .. }
+            | DnsRecord::NS { ref mut ttl, .. }
+            | DnsRecord::CNAME { ref mut ttl, .. }
+            | DnsRecord::SOA { ref mut ttl, .. }
+            | DnsRecord::PTR { ref mut ttl, .. }
+            | DnsRecord::MX { ref mut ttl, .. }
+            | DnsRecord::TXT { ref mut ttl, .. }
+            | DnsRecord::AAAA { ref mut ttl, .. }
+            | DnsRecord::SRV { ref mut ttl, .. } => *ttl = new_ttl,
+            DnsRecord::OPT { .. } => {}
+        }
+    }
+
+    /// The RDATA of the record in wire format, without name compression.
+    pub fn rdata(&self) -> Result<Vec<u8>> {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.set_compression(false);
+        self.write(&mut buffer)?;
+
+        // Skip the owner name, type, class, TTL and RDLENGTH.
+        buffer.seek(0)?;
+        buffer.read_qname()?;
+        buffer.step(10)?;
+
+        Ok(buffer.buf[buffer.pos()..].to_vec())
+    }
+}
+
+/// Formats raw RDATA in the generic text form of RFC 3597, e.g. `\# 4 0a000001`.
+pub fn format_generic_rdata(data: &[u8]) -> String {
+    let mut result = format!("\\# {}", data.len());
+    if !data.is_empty() {
+        result.push(' ');
+        for b in data {
+            result.push_str(&format!("{:02x}", b));
+        }
+    }
+    result
+}
+
+/// Parses the generic RDATA text form of RFC 3597. The hex data may be split
+/// into several whitespace separated words, and its length has to match the
+/// declared length.
+pub fn parse_generic_rdata(s: &str) -> Result<Vec<u8>> {
+    let invalid = || DnsError::syntax(format!("Invalid generic RDATA {}", s));
+
+    let mut words = s.split_whitespace();
+    if words.next() != Some("\\#") {
+        return Err(invalid());
+    }
+    let len = words
+        .next()
+        .and_then(|len| len.parse::<usize>().ok())
+        .ok_or_else(invalid)?;
+
+    let hex: String = words.collect();
+    if hex.len() != len * 2 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut data = Vec::with_capacity(len);
+    for i in 0..len {
+        let byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+        data.push(byte);
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A record owned by the root in class IN, with the given RDLENGTH and
+    /// RDATA in wire format.
+    fn record(qtype: u16, data_len: u16, data: &[u8]) -> Vec<u8> {
+        let mut wire = vec![0];
+        wire.extend(&qtype.to_be_bytes());
+        wire.extend(&1u16.to_be_bytes());
+        wire.extend(&300u32.to_be_bytes());
+        wire.extend(&data_len.to_be_bytes());
+        wire.extend(data);
+        wire
+    }
+
+    fn read(wire: &[u8]) -> Result<DnsRecord> {
+        DnsRecord::read(&mut VectorPacketBuffer::from_bytes(wire))
+    }
+
+    #[test]
+    fn reads_rdata_of_the_declared_length() {
+        let wire = record(1, 4, &[192, 0, 2, 1]);
+        let mut buffer = VectorPacketBuffer::from_bytes(&wire);
+        let rec = DnsRecord::read(&mut buffer).unwrap();
+        assert_eq!(rec.to_string(), ". 300 IN A 192.0.2.1");
+        assert_eq!(buffer.pos(), wire.len());
+    }
+
+    #[test]
+    fn rejects_rdata_shorter_than_rdlength() {
+        let err = read(&record(1, 5, &[192, 0, 2, 1, 0])).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DnsErrorKind::BadRdataLength {
+                expected: 5,
+                actual: 4
+            }
+        );
+        assert_eq!(err.offset, 11);
+    }
+
+    #[test]
+    fn rejects_rdata_longer_than_rdlength() {
+        let err = read(&record(1, 3, &[192, 0, 2, 1])).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DnsErrorKind::BadRdataLength {
+                expected: 3,
+                actual: 4
+            }
+        );
+
+        // The target name runs on past the end of the RDATA.
+        let err = read(&record(2, 2, b"\x02ns\x00")).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DnsErrorKind::BadRdataLength {
+                expected: 2,
+                actual: 4
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_character_strings_overrunning_rdlength() {
+        let err = read(&record(16, 3, b"\x05hello")).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DnsErrorKind::BadRdataLength {
+                expected: 3,
+                actual: 6
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_edns_options_overrunning_rdlength() {
+        let err = read(&record(41, 4, &[0, 10, 0, 2, 1, 2])).unwrap_err();
+        assert_eq!(
+            err.kind,
+            DnsErrorKind::BadRdataLength {
+                expected: 4,
+                actual: 6
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_rdlength_past_the_end_of_the_message() {
+        let err = read(&record(99, 10, &[1, 2, 3])).unwrap_err();
+        assert_eq!(err.kind, DnsErrorKind::EndOfBuffer);
+    }
+
+    #[test]
+    fn splits_long_txt_strings() {
+        let long = vec![b'x'; 300];
+        let rec = DnsRecord::TXT {
+            domain: Name::root(),
+            data: vec![long.clone(), b"end".to_vec()],
+            class: QueryClass::IN,
+            ttl: 300,
+        };
+        let mut buffer = VectorPacketBuffer::new();
+        rec.write(&mut buffer).unwrap();
+
+        // 255 bytes, then the other 45, then the next entry.
+        let mut rdata = vec![255];
+        rdata.extend(&long[..255]);
+        rdata.push(45);
+        rdata.extend(&long[255..]);
+        rdata.extend(b"\x03end");
+        assert_eq!(buffer.buf, record(16, rdata.len() as u16, &rdata));
+
+        match read(&buffer.buf).unwrap() {
+            DnsRecord::TXT { data, .. } => {
+                assert_eq!(
+                    data,
+                    vec![long[..255].to_vec(), long[255..].to_vec(), b"end".to_vec()]
+                );
+            }
+            rec => panic!("unexpected record {:?}", rec),
+        }
+    }
 }
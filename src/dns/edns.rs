@@ -0,0 +1,48 @@
+use super::dns_error::Result;
+use super::packet_buffer::PacketBuffer;
+
+/// The UDP payload size this server advertises and accepts when EDNS0 is in
+/// use.
+pub const DEFAULT_EDNS_PAYLOAD_SIZE: u16 = 4096;
+
+/// A single `{code, length, data}` option carried in the RDATA of an OPT
+/// pseudo-record. The option data is kept as raw bytes since its meaning
+/// depends entirely on the option code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct EdnsOption {
+    pub code: u16,
+    pub data: Vec<u8>,
+}
+
+impl EdnsOption {
+    pub fn new(code: u16, data: Vec<u8>) -> EdnsOption {
+        EdnsOption { code, data }
+    }
+
+    /// Reads all options contained in `data_len` bytes of OPT RDATA.
+    pub fn read_all<T: PacketBuffer>(buffer: &mut T, data_len: u16) -> Result<Vec<EdnsOption>> {
+        let end = buffer.pos() + data_len as usize;
+
+        let mut options = Vec::new();
+        while buffer.pos() < end {
+            let code = buffer.read_u16()?;
+            let len = buffer.read_u16()?;
+            let data = buffer.get_range(buffer.pos(), len as usize)?.to_vec();
+            buffer.step(len as usize)?;
+
+            options.push(EdnsOption { code, data });
+        }
+
+        Ok(options)
+    }
+
+    pub fn write<T: PacketBuffer>(&self, buffer: &mut T) -> Result<()> {
+        buffer.write_u16(self.code)?;
+        buffer.write_u16(self.data.len() as u16)?;
+        for b in &self.data {
+            buffer.write_u8(*b)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt;
+
+use super::edns::EdnsOption;
+
+/// The EDNS0 option code for Extended DNS Errors (RFC 8914).
+pub const EDE_OPTION_CODE: u16 = 15;
+
+/// An INFO-CODE from the IANA "Extended DNS Error Codes" registry.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExtendedErrorCode {
+    Unknown(u16),
+    Other,                      // 0
+    UnsupportedDnskeyAlgorithm, // 1
+    UnsupportedDsDigestType,    // 2
+    StaleAnswer,                // 3
+    ForgedAnswer,               // 4
+    DnssecIndeterminate,        // 5
+    DnssecBogus,                // 6
+    SignatureExpired,           // 7
+    SignatureNotYetValid,       // 8
+    DnskeyMissing,              // 9
+    RrsigsMissing,              // 10
+    NoZoneKeyBitSet,            // 11
+    NsecMissing,                // 12
+    CachedError,                // 13
+    NotReady,                   // 14
+    Blocked,                    // 15
+    Censored,                   // 16
+    Filtered,                   // 17
+    Prohibited,                 // 18
+    StaleNxdomainAnswer,        // 19
+    NotAuthoritative,           // 20
+    NotSupported,               // 21
+    NoReachableAuthority,       // 22
+    NetworkError,               // 23
+    InvalidData,                // 24
+}
+
+impl ExtendedErrorCode {
+    pub fn to_num(self) -> u16 {
+        match self {
+            ExtendedErrorCode::Unknown(x) => x,
+            ExtendedErrorCode::Other => 0,
+            ExtendedErrorCode::UnsupportedDnskeyAlgorithm => 1,
+            ExtendedErrorCode::UnsupportedDsDigestType => 2,
+            ExtendedErrorCode::StaleAnswer => 3,
+            ExtendedErrorCode::ForgedAnswer => 4,
+            ExtendedErrorCode::DnssecIndeterminate => 5,
+            ExtendedErrorCode::DnssecBogus => 6,
+            ExtendedErrorCode::SignatureExpired => 7,
+            ExtendedErrorCode::SignatureNotYetValid => 8,
+            ExtendedErrorCode::DnskeyMissing => 9,
+            ExtendedErrorCode::RrsigsMissing => 10,
+            ExtendedErrorCode::NoZoneKeyBitSet => 11,
+            ExtendedErrorCode::NsecMissing => 12,
+            ExtendedErrorCode::CachedError => 13,
+            ExtendedErrorCode::NotReady => 14,
+            ExtendedErrorCode::Blocked => 15,
+            ExtendedErrorCode::Censored => 16,
+            ExtendedErrorCode::Filtered => 17,
+            ExtendedErrorCode::Prohibited => 18,
+            ExtendedErrorCode::StaleNxdomainAnswer => 19,
+            ExtendedErrorCode::NotAuthoritative => 20,
+            ExtendedErrorCode::NotSupported => 21,
+            ExtendedErrorCode::NoReachableAuthority => 22,
+            ExtendedErrorCode::NetworkError => 23,
+            ExtendedErrorCode::InvalidData => 24,
+        }
+    }
+
+    pub fn from_num(num: u16) -> ExtendedErrorCode {
+        match num {
+            0 => ExtendedErrorCode::Other,
+            1 => ExtendedErrorCode::UnsupportedDnskeyAlgorithm,
+            2 => ExtendedErrorCode::UnsupportedDsDigestType,
+            3 => ExtendedErrorCode::StaleAnswer,
+            4 => ExtendedErrorCode::ForgedAnswer,
+            5 => ExtendedErrorCode::DnssecIndeterminate,
+            6 => ExtendedErrorCode::DnssecBogus,
+            7 => ExtendedErrorCode::SignatureExpired,
+            8 => ExtendedErrorCode::SignatureNotYetValid,
+            9 => ExtendedErrorCode::DnskeyMissing,
+            10 => ExtendedErrorCode::RrsigsMissing,
+            11 => ExtendedErrorCode::NoZoneKeyBitSet,
+            12 => ExtendedErrorCode::NsecMissing,
+            13 => ExtendedErrorCode::CachedError,
+            14 => ExtendedErrorCode::NotReady,
+            15 => ExtendedErrorCode::Blocked,
+            16 => ExtendedErrorCode::Censored,
+            17 => ExtendedErrorCode::Filtered,
+            18 => ExtendedErrorCode::Prohibited,
+            19 => ExtendedErrorCode::StaleNxdomainAnswer,
+            20 => ExtendedErrorCode::NotAuthoritative,
+            21 => ExtendedErrorCode::NotSupported,
+            22 => ExtendedErrorCode::NoReachableAuthority,
+            23 => ExtendedErrorCode::NetworkError,
+            24 => ExtendedErrorCode::InvalidData,
+            _ => ExtendedErrorCode::Unknown(num),
+        }
+    }
+}
+
+/// Formats the purpose given for the code in the registry.
+impl fmt::Display for ExtendedErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let purpose = match *self {
+            ExtendedErrorCode::Unknown(x) => return write!(f, "Unknown Error {}", x),
+            ExtendedErrorCode::Other => "Other Error",
+            ExtendedErrorCode::UnsupportedDnskeyAlgorithm => "Unsupported DNSKEY Algorithm",
+            ExtendedErrorCode::UnsupportedDsDigestType => "Unsupported DS Digest Type",
+            ExtendedErrorCode::StaleAnswer => "Stale Answer",
+            ExtendedErrorCode::ForgedAnswer => "Forged Answer",
+            ExtendedErrorCode::DnssecIndeterminate => "DNSSEC Indeterminate",
+            ExtendedErrorCode::DnssecBogus => "DNSSEC Bogus",
+            ExtendedErrorCode::SignatureExpired => "Signature Expired",
+            ExtendedErrorCode::SignatureNotYetValid => "Signature Not Yet Valid",
+            ExtendedErrorCode::DnskeyMissing => "DNSKEY Missing",
+            ExtendedErrorCode::RrsigsMissing => "RRSIGs Missing",
+            ExtendedErrorCode::NoZoneKeyBitSet => "No Zone Key Bit Set",
+            ExtendedErrorCode::NsecMissing => "NSEC Missing",
+            ExtendedErrorCode::CachedError => "Cached Error",
+            ExtendedErrorCode::NotReady => "Not Ready",
+            ExtendedErrorCode::Blocked => "Blocked",
+            ExtendedErrorCode::Censored => "Censored",
+            ExtendedErrorCode::Filtered => "Filtered",
+            ExtendedErrorCode::Prohibited => "Prohibited",
+            ExtendedErrorCode::StaleNxdomainAnswer => "Stale NXDOMAIN Answer",
+            ExtendedErrorCode::NotAuthoritative => "Not Authoritative",
+            ExtendedErrorCode::NotSupported => "Not Supported",
+            ExtendedErrorCode::NoReachableAuthority => "No Reachable Authority",
+            ExtendedErrorCode::NetworkError => "Network Error",
+            ExtendedErrorCode::InvalidData => "Invalid Data",
+        };
+        write!(f, "{}", purpose)
+    }
+}
+
+/// An Extended DNS Error, which explains why a response is what it is, e.g.
+/// why the server answered SERVFAIL. Carried as an EDNS0 option, of which a
+/// response may have several.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtendedError {
+    pub code: ExtendedErrorCode,
+    /// Free form text for humans, which may be empty.
+    pub extra_text: String,
+}
+
+impl ExtendedError {
+    pub fn new<S: Into<String>>(code: ExtendedErrorCode, extra_text: S) -> ExtendedError {
+        ExtendedError {
+            code,
+            extra_text: extra_text.into(),
+        }
+    }
+
+    /// Decodes an EDE option. Returns `None` for other options, and for EDE
+    /// options too short to hold an INFO-CODE.
+    pub fn from_option(option: &EdnsOption) -> Option<ExtendedError> {
+        if option.code != EDE_OPTION_CODE || option.data.len() < 2 {
+            return None;
+        }
+
+        let code = ((option.data[0] as u16) << 8) | (option.data[1] as u16);
+
+        // The text should be UTF-8, but it's only meant for display so
+        // anything else isn't worth rejecting the option over. Some servers
+        // NUL terminate it.
+        let extra_text = String::from_utf8_lossy(&option.data[2..])
+            .trim_end_matches('\0')
+            .to_string();
+
+        Some(ExtendedError::new(
+            ExtendedErrorCode::from_num(code),
+            extra_text,
+        ))
+    }
+
+    pub fn to_option(&self) -> EdnsOption {
+        let code = self.code.to_num();
+        let mut data = vec![(code >> 8) as u8, (code & 0xFF) as u8];
+        data.extend_from_slice(self.extra_text.as_bytes());
+
+        EdnsOption::new(EDE_OPTION_CODE, data)
+    }
+}
+
+impl fmt::Display for ExtendedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.code.to_num(), self.code)?;
+        if !self.extra_text.is_empty() {
+            write!(f, ": {}", self.extra_text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn option(data: &[u8]) -> EdnsOption {
+        EdnsOption::new(EDE_OPTION_CODE, data.to_vec())
+    }
+
+    #[test]
+    fn round_trips_through_options() {
+        let error = ExtendedError::new(ExtendedErrorCode::NetworkError, "timed out");
+        let option = error.to_option();
+        assert_eq!(option.code, EDE_OPTION_CODE);
+        assert_eq!(option.data, b"\x00\x17timed out");
+        assert_eq!(ExtendedError::from_option(&option), Some(error));
+
+        let error = ExtendedError::new(ExtendedErrorCode::Other, "");
+        assert_eq!(error.to_option().data, [0, 0]);
+        assert_eq!(ExtendedError::from_option(&error.to_option()), Some(error));
+    }
+
+    #[test]
+    fn ignores_short_and_other_options() {
+        assert_eq!(ExtendedError::from_option(&option(b"")), None);
+        assert_eq!(ExtendedError::from_option(&option(b"\x00")), None);
+        assert_eq!(
+            ExtendedError::from_option(&EdnsOption::new(10, vec![0, 22])),
+            None
+        );
+    }
+
+    #[test]
+    fn trims_nul_terminated_text() {
+        let error = ExtendedError::from_option(&option(b"\x00\x16no servers\x00")).unwrap();
+        assert_eq!(error.code, ExtendedErrorCode::NoReachableAuthority);
+        assert_eq!(error.extra_text, "no servers");
+
+        // Text that isn't UTF-8 is kept for display rather than rejected.
+        let error = ExtendedError::from_option(&option(b"\x00\x00a\xffb")).unwrap();
+        assert_eq!(error.extra_text, "a\u{fffd}b");
+    }
+
+    #[test]
+    fn keeps_unknown_codes() {
+        let error = ExtendedError::from_option(&option(b"\x01\x2cnew")).unwrap();
+        assert_eq!(error.code, ExtendedErrorCode::Unknown(300));
+        assert_eq!(error.to_option().data, b"\x01\x2cnew");
+        assert_eq!(error.to_string(), "300 (Unknown Error 300): new");
+
+        assert_eq!(ExtendedErrorCode::from_num(22).to_num(), 22);
+        assert_eq!(
+            ExtendedErrorCode::from_num(25),
+            ExtendedErrorCode::Unknown(25)
+        );
+    }
+}
@@ -1,9 +1,22 @@
 //! This module contains and implements DNS data primitives.
 
 pub mod byte_packet_buffer;
-mod dns_header;
+pub mod dns_error;
+pub mod dns_header;
 pub mod dns_packet;
+pub mod dns_packet_ref;
 pub mod dns_question;
-mod dns_record;
+pub mod dns_record;
+pub mod edns;
+pub mod extended_error;
+pub mod name;
+pub mod opcode;
+pub mod packet_buffer;
+pub mod presentation;
+pub mod query_class;
 pub mod query_type;
 pub mod result_code;
+pub mod reverse;
+#[cfg(feature = "serde")]
+mod serde_impls;
+pub mod vector_packet_buffer;
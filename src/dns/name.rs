@@ -0,0 +1,451 @@
+//! Domain names as sequences of labels.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+use rand::random;
+
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::packet_buffer::MAX_NAME_LEN;
+
+/// The longest a single label may be.
+pub const MAX_LABEL_LEN: usize = 63;
+
+/// A domain name, stored as its labels without the terminating root label.
+/// Labels are arbitrary bytes and keep the case they were created with, but
+/// names compare and hash ASCII case-insensitively as RFC 4343 requires.
+///
+/// Ordering is the canonical DNS order of RFC 4034 section 6.1, which sorts by
+/// the rightmost label first, so that a zone's names sort together.
+#[derive(Clone, Debug, Default)]
+pub struct Name {
+    labels: Vec<Vec<u8>>,
+}
+
+impl Name {
+    /// The root name, `.`.
+    pub const fn root() -> Name {
+        Name { labels: Vec::new() }
+    }
+
+    /// Builds a name from its labels, leftmost first. Fails if a label is
+    /// empty or longer than 63 bytes, or if the name would be longer than 255
+    /// bytes in wire format.
+    pub fn from_labels<I, L>(labels: I) -> Result<Name>
+    where
+        I: IntoIterator<Item = L>,
+        L: AsRef<[u8]>,
+    {
+        let mut name = Name::root();
+        for label in labels {
+            let label = label.as_ref();
+            if label.is_empty() {
+                return Err(DnsError::syntax("Empty label in domain name"));
+            }
+            if label.len() > MAX_LABEL_LEN {
+                return Err(DnsError::new(DnsErrorKind::LabelTooLong, 0));
+            }
+            name.labels.push(label.to_vec());
+        }
+
+        if name.wire_len() > MAX_NAME_LEN {
+            return Err(DnsError::new(DnsErrorKind::NameTooLong, 0));
+        }
+
+        Ok(name)
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// The number of labels, not counting the root label.
+    pub fn num_labels(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Iterates over the labels, leftmost first.
+    pub fn labels(&self) -> impl DoubleEndedIterator<Item = &[u8]> + ExactSizeIterator {
+        self.labels.iter().map(|label| label.as_slice())
+    }
+
+    /// The length of the name in uncompressed wire format, including the
+    /// length bytes and the root label.
+    pub fn wire_len(&self) -> usize {
+        self.labels
+            .iter()
+            .map(|label| label.len() + 1)
+            .sum::<usize>()
+            + 1
+    }
+
+    /// The name with its leftmost label removed, or `None` for the root.
+    pub fn parent(&self) -> Option<Name> {
+        if self.is_root() {
+            return None;
+        }
+        Some(self.suffix(1))
+    }
+
+    /// The name with its first `skip` labels removed.
+    pub fn suffix(&self, skip: usize) -> Name {
+        Name {
+            labels: self.labels[skip.min(self.labels.len())..].to_vec(),
+        }
+    }
+
+    /// Whether this name is `other` or lies below it. Unlike a string suffix
+    /// check, `badexample.com` is not a subdomain of `example.com`.
+    pub fn is_subdomain_of(&self, other: &Name) -> bool {
+        if other.labels.len() > self.labels.len() {
+            return false;
+        }
+
+        self.labels
+            .iter()
+            .rev()
+            .zip(other.labels.iter().rev())
+            .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+
+    /// Compares the names byte for byte, including case. Used to check that a
+    /// response echoes the question of a query exactly.
+    pub fn eq_exact(&self, other: &Name) -> bool {
+        self.labels == other.labels
+    }
+
+    /// Returns a copy with the case of every ASCII letter chosen at random.
+    /// Since servers echo the question as sent, this makes responses harder
+    /// to forge (the "0x20" defense of draft-vixie-dnsext-dns0x20).
+    pub fn with_random_case(&self) -> Name {
+        let labels = self
+            .labels
+            .iter()
+            .map(|label| {
+                label
+                    .iter()
+                    .map(|&b| {
+                        if b.is_ascii_alphabetic() && random::<bool>() {
+                            b ^ 0x20
+                        } else {
+                            b
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+
+        Name { labels }
+    }
+
+    /// Returns a copy with all ASCII letters lowercased, as used in the
+    /// canonical form of RFC 4034.
+    pub fn to_lowercase(&self) -> Name {
+        Name {
+            labels: self
+                .labels
+                .iter()
+                .map(|label| label.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+}
+
+impl PartialEq for Name {
+    fn eq(&self, other: &Name) -> bool {
+        self.labels.len() == other.labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(other.labels.iter())
+                .all(|(a, b)| a.eq_ignore_ascii_case(b))
+    }
+}
+
+impl Eq for Name {}
+
+impl Hash for Name {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.labels.len());
+        for label in &self.labels {
+            state.write_usize(label.len());
+            for b in label {
+                state.write_u8(b.to_ascii_lowercase());
+            }
+        }
+    }
+}
+
+impl Ord for Name {
+    fn cmp(&self, other: &Name) -> Ordering {
+        for (a, b) in self.labels.iter().rev().zip(other.labels.iter().rev()) {
+            let a = a.iter().map(u8::to_ascii_lowercase);
+            let b = b.iter().map(u8::to_ascii_lowercase);
+            match a.cmp(b) {
+                Ordering::Equal => continue,
+                ord => return ord,
+            }
+        }
+
+        // One name is a suffix of the other, so the one with fewer labels,
+        // i.e. the parent, goes first.
+        self.labels.len().cmp(&other.labels.len())
+    }
+}
+
+impl PartialOrd for Name {
+    fn partial_cmp(&self, other: &Name) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Formats the name in presentation format, as a fully qualified name with a
+/// trailing dot. Dots and backslashes inside labels are escaped, and bytes
+/// that aren't printable are written as `\DDD`.
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_root() {
+            return write!(f, ".");
+        }
+
+        for label in &self.labels {
+            fmt_label(f, label)?;
+            write!(f, ".")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a single label in presentation format, escaping as needed.
+pub(crate) fn fmt_label<W: fmt::Write>(f: &mut W, label: &[u8]) -> fmt::Result {
+    for &b in label {
+        match b {
+            b'.' | b'\\' | b'"' | b';' | b'(' | b')' => write!(f, "\\{}", b as char)?,
+            0x21..=0x7E => write!(f, "{}", b as char)?,
+            _ => write!(f, "\\{:03}", b)?,
+        }
+    }
+    Ok(())
+}
+
+/// Parses a name in presentation format. The trailing dot is optional, as all
+/// names are taken to be fully qualified, and `\X` and `\DDD` escapes are
+/// resolved.
+impl FromStr for Name {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<Name> {
+        if s == "." {
+            return Ok(Name::root());
+        }
+        if s.is_empty() {
+            return Err(DnsError::syntax("Empty domain name"));
+        }
+
+        let bytes = s.as_bytes();
+        let mut labels = Vec::new();
+        let mut label = Vec::new();
+
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'.' => {
+                    if label.is_empty() {
+                        return Err(DnsError::new(
+                            DnsErrorKind::Syntax(format!("Empty label in {}", s)),
+                            i,
+                        ));
+                    }
+                    labels.push(std::mem::take(&mut label));
+                    i += 1;
+                }
+                b'\\' => {
+                    let digits = bytes.get(i + 1..i + 4).unwrap_or(&[]);
+                    if digits.len() == 3 && digits.iter().all(u8::is_ascii_digit) {
+                        let value = digits
+                            .iter()
+                            .fold(0u32, |acc, d| acc * 10 + (d - b'0') as u32);
+                        if value > 255 {
+                            return Err(DnsError::new(
+                                DnsErrorKind::Syntax(format!("Invalid escape in {}", s)),
+                                i,
+                            ));
+                        }
+                        label.push(value as u8);
+                        i += 4;
+                    } else if let Some(&b) = bytes.get(i + 1) {
+                        label.push(b);
+                        i += 2;
+                    } else {
+                        return Err(DnsError::new(
+                            DnsErrorKind::Syntax(format!("Dangling escape in {}", s)),
+                            i,
+                        ));
+                    }
+                }
+                b => {
+                    label.push(b);
+                    i += 1;
+                }
+            }
+        }
+        if !label.is_empty() {
+            labels.push(label);
+        }
+
+        Name::from_labels(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    fn hash(name: &Name) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        name.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn subdomains_match_whole_labels() {
+        let example = name("example.com");
+        assert!(name("www.example.com").is_subdomain_of(&example));
+        assert!(name("WWW.Example.COM").is_subdomain_of(&example));
+        assert!(example.is_subdomain_of(&example));
+        assert!(example.is_subdomain_of(&Name::root()));
+
+        assert!(!name("badexample.com").is_subdomain_of(&example));
+        assert!(!name("com").is_subdomain_of(&example));
+        assert!(!name("example.net").is_subdomain_of(&example));
+        assert!(!Name::root().is_subdomain_of(&example));
+    }
+
+    #[test]
+    fn eq_and_hash_ignore_case() {
+        let lower = name("www.example.com");
+        let mixed = name("WwW.eXaMpLe.CoM.");
+        assert_eq!(lower, mixed);
+        assert_eq!(hash(&lower), hash(&mixed));
+        assert!(!lower.eq_exact(&mixed));
+        assert!(lower.eq_exact(&mixed.to_lowercase()));
+
+        let set: HashSet<Name> = vec![lower.clone(), mixed.clone()].into_iter().collect();
+        assert_eq!(set.len(), 1);
+
+        // Label boundaries matter, not just the bytes.
+        assert_ne!(name("ab.c"), name("a.bc"));
+        assert_ne!(hash(&name("ab.c")), hash(&name("a.bc")));
+        assert_ne!(name("example.com"), name("www.example.com"));
+    }
+
+    #[test]
+    fn random_case_keeps_the_name() {
+        let name = name("some-long-name.with.many.letters.example.com");
+        let random = name.with_random_case();
+        assert_eq!(random, name);
+        assert_eq!(random.to_lowercase().to_string(), name.to_string());
+    }
+
+    #[test]
+    fn sorts_in_canonical_order() {
+        // The example from RFC 4034 section 6.1.
+        let expected = [
+            "example",
+            "a.example",
+            "yljkjljk.a.example",
+            "Z.a.example",
+            "zABC.a.EXAMPLE",
+            "z.example",
+            "\\001.z.example",
+            "*.z.example",
+            "\\200.z.example",
+        ];
+
+        let mut names: Vec<Name> = expected.iter().rev().map(|s| name(s)).collect();
+        names.sort();
+        let sorted: Vec<String> = names.iter().map(|n| n.to_string()).collect();
+        let expected: Vec<String> = expected.iter().map(|s| format!("{}.", s)).collect();
+        assert_eq!(sorted, expected);
+
+        assert!(Name::root() < name("example"));
+        assert_eq!(name("A.example").cmp(&name("a.EXAMPLE")), Ordering::Equal);
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        let cases = [
+            ("a\\.b.example.", vec![&b"a.b"[..], b"example"]),
+            ("back\\\\slash.", vec![&b"back\\slash"[..]]),
+            ("\\000\\255\\032.", vec![&b"\x00\xff "[..]]),
+            (r#"\"\;\(\)."#, vec![&b"\";()"[..]]),
+        ];
+        for (text, labels) in cases.iter() {
+            let parsed = name(text);
+            assert_eq!(parsed.labels().collect::<Vec<_>>(), *labels);
+            assert_eq!(parsed.to_string(), *text);
+            assert!(name(&parsed.to_string()).eq_exact(&parsed));
+        }
+
+        // Escapes that needn't be escaped read back as the plain character.
+        assert_eq!(name("\\a\\098c").to_string(), "abc.");
+        assert_eq!(Name::root().to_string(), ".");
+        assert!(name(".").is_root());
+    }
+
+    #[test]
+    fn rejects_bad_presentation_names() {
+        for text in &["", "..", "a..b", ".a", "a\\", "\\256.example", "a.b\\"] {
+            assert!(text.parse::<Name>().is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn limits_label_and_name_length() {
+        let label = [b'a'; MAX_LABEL_LEN];
+        assert!(Name::from_labels([&label[..]]).is_ok());
+        let long = [b'a'; MAX_LABEL_LEN + 1];
+        assert_eq!(
+            Name::from_labels([&long[..]]).unwrap_err().kind,
+            DnsErrorKind::LabelTooLong
+        );
+        assert!(Name::from_labels([&b""[..]]).is_err());
+
+        // Three 63 byte labels and one of 61 make a name of exactly 255 bytes.
+        let mut labels = vec![&label[..]; 3];
+        labels.push(&label[..61]);
+        let max = Name::from_labels(&labels).unwrap();
+        assert_eq!(max.wire_len(), MAX_NAME_LEN);
+        assert_eq!(max.num_labels(), 4);
+
+        labels[3] = &label[..62];
+        assert_eq!(
+            Name::from_labels(&labels).unwrap_err().kind,
+            DnsErrorKind::NameTooLong
+        );
+        let text = max.to_string();
+        assert!(text.parse::<Name>().is_ok());
+        assert!(format!("a.{}", text).parse::<Name>().is_err());
+    }
+
+    #[test]
+    fn parent_and_suffix() {
+        let www = name("www.example.com");
+        assert_eq!(www.parent(), Some(name("example.com")));
+        assert_eq!(www.suffix(2), name("com"));
+        assert!(www.suffix(5).is_root());
+        assert_eq!(Name::root().parent(), None);
+        assert_eq!(Name::root().wire_len(), 1);
+        assert_eq!(www.wire_len(), 17);
+    }
+}
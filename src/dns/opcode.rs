@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// The kind of request a message carries, from the 4 bit OPCODE header field.
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy)]
+pub enum Opcode {
+    UNKNOWN(u8),
+    QUERY,  // 0
+    IQUERY, // 1, obsoleted by RFC 3425
+    STATUS, // 2
+    NOTIFY, // 4, RFC 1996
+    UPDATE, // 5, RFC 2136
+    DSO,    // 6, RFC 8490
+}
+
+impl Opcode {
+    pub fn to_num(self) -> u8 {
+        match self {
+            Opcode::UNKNOWN(x) => x,
+            Opcode::QUERY => 0,
+            Opcode::IQUERY => 1,
+            Opcode::STATUS => 2,
+            Opcode::NOTIFY => 4,
+            Opcode::UPDATE => 5,
+            Opcode::DSO => 6,
+        }
+    }
+
+    /// Only the lower 4 bits of `num` are used, as that is all the header
+    /// has room for.
+    pub fn from_num(num: u8) -> Opcode {
+        match num & 0x0F {
+            0 => Opcode::QUERY,
+            1 => Opcode::IQUERY,
+            2 => Opcode::STATUS,
+            4 => Opcode::NOTIFY,
+            5 => Opcode::UPDATE,
+            6 => Opcode::DSO,
+            x => Opcode::UNKNOWN(x),
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Opcode::UNKNOWN(x) => write!(f, "OPCODE{}", x),
+            Opcode::QUERY => write!(f, "QUERY"),
+            Opcode::IQUERY => write!(f, "IQUERY"),
+            Opcode::STATUS => write!(f, "STATUS"),
+            Opcode::NOTIFY => write!(f, "NOTIFY"),
+            Opcode::UPDATE => write!(f, "UPDATE"),
+            Opcode::DSO => write!(f, "DSO"),
+        }
+    }
+}
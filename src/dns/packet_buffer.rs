@@ -0,0 +1,480 @@
+use std::collections::HashMap;
+
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::name::Name;
+
+/// The longest a domain name may be in wire format, including the length
+/// bytes and the terminating root label.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// How many compression pointers `read_qname` follows for a single name
+/// before giving up.
+pub const MAX_JUMPS: usize = 32;
+
+/// The names a buffer has written so far, for buffers that compress names.
+/// Such buffers keep one of these and forward `find_label`, `save_label` and
+/// `compression` to it.
+pub struct NameCompression {
+    /// Positions of names (and name suffixes) already written to the buffer,
+    /// keyed by their presentation form. Used to emit compression pointers.
+    labels: HashMap<String, usize>,
+    enabled: bool,
+}
+
+impl Default for NameCompression {
+    fn default() -> Self {
+        NameCompression::new()
+    }
+}
+
+impl NameCompression {
+    pub fn new() -> NameCompression {
+        NameCompression {
+            labels: HashMap::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn find(&self, label: &str) -> Option<usize> {
+        self.labels.get(label).cloned()
+    }
+
+    pub fn save(&mut self, label: &str, pos: usize) {
+        self.labels.insert(label.to_string(), pos);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Enables or disables name compression in `write_qname`. Compression is
+    /// on by default, but must be turned off when producing the canonical
+    /// wire format used by DNSSEC.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// Common interface for buffers that DNS packets can be read from and written
+/// to. Implementors only provide the raw byte access; the integer and domain
+/// name codecs are shared through the provided methods.
+pub trait PacketBuffer {
+    fn read(&mut self) -> Result<u8>;
+    fn get(&mut self, pos: usize) -> Result<u8>;
+    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]>;
+    fn write(&mut self, val: u8) -> Result<()>;
+    fn set(&mut self, pos: usize, val: u8) -> Result<()>;
+    fn pos(&self) -> usize;
+    fn seek(&mut self, pos: usize) -> Result<()>;
+    fn step(&mut self, steps: usize) -> Result<()>;
+
+    /// Looks up the position of a previously written name suffix, keyed by
+    /// its presentation form. Buffers that don't compress names never find
+    /// one.
+    fn find_label(&self, _label: &str) -> Option<usize> {
+        None
+    }
+
+    fn save_label(&mut self, _label: &str, _pos: usize) {}
+
+    /// Whether `write_qname` should emit compression pointers. Buffers opt
+    /// in by overriding this along with `find_label` and `save_label`.
+    fn compression(&self) -> bool {
+        false
+    }
+
+    // Methods for reading 1, 2, or 4 bytes.
+
+    fn read_u16(&mut self) -> Result<u16> {
+        let res = ((self.read()? as u16) << 8) | (self.read()? as u16);
+        Ok(res)
+    }
+
+    fn read_u32(&mut self) -> Result<u32> {
+        let res = ((self.read()? as u32) << 24)
+            | ((self.read()? as u32) << 16)
+            | ((self.read()? as u32) << 8)
+            | (self.read()? as u32);
+        Ok(res)
+    }
+
+    // Methods for writing
+
+    fn write_u8(&mut self, val: u8) -> Result<()> {
+        self.write(val)?;
+        Ok(())
+    }
+
+    fn write_u16(&mut self, val: u16) -> Result<()> {
+        self.write((val >> 8) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+        Ok(())
+    }
+
+    fn write_u32(&mut self, val: u32) -> Result<()> {
+        self.write(((val >> 24) & 0xFF) as u8)?;
+        self.write(((val >> 16) & 0xFF) as u8)?;
+        self.write(((val >> 8) & 0xFF) as u8)?;
+        self.write((val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+
+    // Methods for setting
+
+    fn set_u16(&mut self, pos: usize, val: u16) -> Result<()> {
+        self.set(pos, (val >> 8) as u8)?;
+        self.set(pos + 1, (val & 0xFF) as u8)?;
+
+        Ok(())
+    }
+
+    /// The tricky part is in reading domain names and taking labels into
+    /// consideration. Since the input may be hostile, every compression
+    /// pointer has to point before the part of the name read so far, which
+    /// rules out loops, and the number of jumps and the total length of the
+    /// name are bounded.
+    fn read_qname(&mut self) -> Result<Name> {
+        let mut pos = self.pos();
+        let mut jumped = false;
+        let mut jumps = 0;
+
+        // Where the stretch of labels we're currently reading starts. A pointer
+        // must jump to somewhere before this.
+        let mut segment_start = pos;
+
+        // The length of the name in wire format, without compression.
+        let mut name_len = 0;
+
+        let mut labels = Vec::new();
+        loop {
+            let len = self.get(pos)?;
+
+            // If `len` has the 2 most significant bits set, it represents a jump
+            // to some other offset in this packet.
+            if (len & 0xC0) == 0xC0 {
+                if jumps == MAX_JUMPS {
+                    return Err(DnsError::new(DnsErrorKind::TooManyJumps, pos));
+                }
+
+                // Read another byte, calculate the offset of the jump.
+                let b2 = self.get(pos + 1)? as u16;
+                let offset = ((((len as u16) ^ 0xC0) << 8) | b2) as usize;
+                if offset >= segment_start {
+                    return Err(DnsError::new(
+                        DnsErrorKind::BadPointer { target: offset },
+                        pos,
+                    ));
+                }
+
+                // Update the buffer position to a point past the current label.
+                if !jumped {
+                    self.seek(pos + 2)?;
+                }
+
+                // Perform the jump by updating our local position variable.
+                pos = offset;
+                segment_start = offset;
+
+                jumped = true;
+                jumps += 1;
+            } else if (len & 0xC0) != 0 {
+                return Err(DnsError::new(DnsErrorKind::BadLabel { len }, pos));
+            } else {
+                pos += 1;
+
+                name_len += len as usize + 1;
+                if name_len > MAX_NAME_LEN {
+                    return Err(DnsError::new(DnsErrorKind::NameTooLong, pos));
+                }
+
+                // Domain names are terminated by an empty label of length 0.
+                if len == 0 {
+                    break;
+                }
+
+                // The case is kept as it was sent, so that it can be echoed
+                // back exactly. `Name` compares case-insensitively.
+                let label = self.get_range(pos, len as usize)?;
+                labels.push(label.to_vec());
+
+                // Move forward the full length of the label.
+                pos += len as usize;
+            }
+        }
+
+        // If a jump was performed, we've already modified the buffer position state
+        // and shouldn't do it again.
+        if !jumped {
+            self.seek(pos)?;
+        }
+
+        // The checks above already enforce the limits on label and name length.
+        Name::from_labels(labels)
+    }
+
+    /// Writes a domain name, replacing any suffix that has already been
+    /// written to the packet with a pointer to its earlier occurrence.
+    fn write_qname(&mut self, qname: &Name) -> Result<()> {
+        let compress = self.compression();
+        self.write_qname_with(qname, compress)
+    }
+
+    /// Writes a domain name in full, for fields where compression is not
+    /// allowed such as the SRV target.
+    fn write_qname_uncompressed(&mut self, qname: &Name) -> Result<()> {
+        self.write_qname_with(qname, false)
+    }
+
+    fn write_qname_with(&mut self, qname: &Name, compress: bool) -> Result<()> {
+        for (i, label) in qname.labels().enumerate() {
+            if compress {
+                // Suffixes are matched with their case, since the name read
+                // back through a pointer has the case of its target.
+                let suffix = qname.suffix(i).to_string();
+
+                // The rest of the name has been written before, so point to it
+                // and we're done. A pointer terminates the name by itself.
+                if let Some(prev_pos) = self.find_label(&suffix) {
+                    self.write_u16(0xC000 | prev_pos as u16)?;
+                    return Ok(());
+                }
+
+                // Pointers only have 14 bits available for the offset.
+                let pos = self.pos();
+                if pos <= 0x3FFF {
+                    self.save_label(&suffix, pos);
+                }
+            }
+
+            self.write_u8(label.len() as u8)?;
+            for b in label {
+                self.write_u8(*b)?;
+            }
+        }
+
+        self.write_u8(0)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::vector_packet_buffer::VectorPacketBuffer;
+
+    /// Reads a name starting at `pos` in `data`.
+    fn read_at(data: &[u8], pos: usize) -> Result<(Name, usize)> {
+        let mut buffer = VectorPacketBuffer::from_bytes(data);
+        buffer.seek(pos)?;
+        let name = buffer.read_qname()?;
+        Ok((name, buffer.pos()))
+    }
+
+    fn kind_at(data: &[u8], pos: usize) -> DnsErrorKind {
+        read_at(data, pos).unwrap_err().kind
+    }
+
+    /// A name made of labels of the given lengths, in wire format.
+    fn labels(lens: &[usize]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &len in lens {
+            data.push(len as u8);
+            data.resize(data.len() + len, b'a');
+        }
+        data
+    }
+
+    #[test]
+    fn reads_plain_and_compressed_names() {
+        let data = b"\x03www\x07example\x03com\x00\x04mail\xc0\x04";
+
+        let (name, pos) = read_at(data, 0).unwrap();
+        assert_eq!(name.to_string(), "www.example.com.");
+        assert_eq!(pos, 17);
+
+        // The position ends up after the pointer, not after the name it
+        // points to.
+        let (name, pos) = read_at(data, 17).unwrap();
+        assert_eq!(name.to_string(), "mail.example.com.");
+        assert_eq!(pos, data.len());
+    }
+
+    #[test]
+    fn keeps_the_case_of_names() {
+        let (name, _) = read_at(b"\x03WwW\x00", 0).unwrap();
+        assert!(name.eq_exact(&Name::from_labels(vec![b"WwW".to_vec()]).unwrap()));
+    }
+
+    #[test]
+    fn rejects_pointer_to_itself() {
+        assert_eq!(
+            kind_at(b"\xc0\x00", 0),
+            DnsErrorKind::BadPointer { target: 0 }
+        );
+    }
+
+    #[test]
+    fn rejects_pointer_loops() {
+        // The name at 4 points to 0, which points back to 4.
+        let data = b"\x01a\xc0\x04\xc0\x00";
+        assert_eq!(kind_at(data, 4), DnsErrorKind::BadPointer { target: 4 });
+    }
+
+    #[test]
+    fn rejects_forward_pointers() {
+        let data = b"\xc0\x02\x01a\x00";
+        assert_eq!(kind_at(data, 0), DnsErrorKind::BadPointer { target: 2 });
+    }
+
+    #[test]
+    fn rejects_pointer_into_the_same_name() {
+        // The pointer lands on the second label of the name it is part of.
+        let data = b"\x01a\x01b\xc0\x02";
+        assert_eq!(kind_at(data, 0), DnsErrorKind::BadPointer { target: 2 });
+    }
+
+    /// The root name at 0 followed by `count` pointers, each pointing to the
+    /// one before it.
+    fn pointer_chain(count: usize) -> Vec<u8> {
+        let mut data = vec![0];
+        let mut prev = 0;
+        for _ in 0..count {
+            let pos = data.len();
+            data.push(0xc0);
+            data.push(prev as u8);
+            prev = pos;
+        }
+        data
+    }
+
+    #[test]
+    fn follows_up_to_max_jumps() {
+        let data = pointer_chain(MAX_JUMPS);
+        let (name, pos) = read_at(&data, data.len() - 2).unwrap();
+        assert!(name.is_root());
+        assert_eq!(pos, data.len());
+    }
+
+    #[test]
+    fn rejects_more_than_max_jumps() {
+        let data = pointer_chain(MAX_JUMPS + 1);
+        assert_eq!(kind_at(&data, data.len() - 2), DnsErrorKind::TooManyJumps);
+    }
+
+    #[test]
+    fn accepts_names_of_max_len() {
+        let mut data = labels(&[63, 63, 63, 61]);
+        data.push(0);
+        assert_eq!(data.len(), MAX_NAME_LEN);
+
+        let (name, _) = read_at(&data, 0).unwrap();
+        assert_eq!(name.wire_len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn rejects_names_over_max_len() {
+        let mut data = labels(&[63, 63, 63, 62]);
+        data.push(0);
+        assert_eq!(kind_at(&data, 0), DnsErrorKind::NameTooLong);
+    }
+
+    #[test]
+    fn rejects_names_over_max_len_through_pointers() {
+        // Each half is fine on its own, but not once the pointer joins them.
+        let mut data = labels(&[63, 63]);
+        data.push(0);
+        let start = data.len();
+        data.extend(labels(&[63, 63]));
+        data.extend(&[0xc0, 0x00]);
+        assert_eq!(kind_at(&data, start), DnsErrorKind::NameTooLong);
+    }
+
+    #[test]
+    fn rejects_reserved_label_types() {
+        assert_eq!(
+            kind_at(b"\x40a\x00", 0),
+            DnsErrorKind::BadLabel { len: 0x40 }
+        );
+        assert_eq!(
+            kind_at(b"\x80a\x00", 0),
+            DnsErrorKind::BadLabel { len: 0x80 }
+        );
+        assert_eq!(
+            kind_at(b"\x01a\x7f\x00", 0),
+            DnsErrorKind::BadLabel { len: 0x7f }
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_names() {
+        assert_eq!(kind_at(b"\x03ww", 0), DnsErrorKind::EndOfBuffer);
+        assert_eq!(kind_at(b"\x01a", 0), DnsErrorKind::EndOfBuffer);
+        assert_eq!(kind_at(b"\x00\xc0", 1), DnsErrorKind::EndOfBuffer);
+    }
+
+    fn write_names(buffer: &mut VectorPacketBuffer, names: &[&str]) {
+        for name in names {
+            buffer.write_qname(&name.parse().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn writes_pointers_to_the_longest_known_suffix() {
+        let mut buffer = VectorPacketBuffer::new();
+        write_names(
+            &mut buffer,
+            &[
+                "example.com.",
+                "mail.example.com.",
+                "www.mail.example.com.",
+                "com.",
+            ],
+        );
+        assert_eq!(
+            buffer.buf,
+            b"\x07example\x03com\x00\x04mail\xc0\x00\x03www\xc0\x0d\xc0\x08".to_vec()
+        );
+
+        buffer.seek(20).unwrap();
+        assert_eq!(
+            buffer.read_qname().unwrap().to_string(),
+            "www.mail.example.com."
+        );
+    }
+
+    #[test]
+    fn writes_full_names_without_compression() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.set_compression(false);
+        write_names(&mut buffer, &["example.com.", "example.com."]);
+        assert_eq!(
+            buffer.buf,
+            b"\x07example\x03com\x00\x07example\x03com\x00".to_vec()
+        );
+
+        let mut buffer = VectorPacketBuffer::new();
+        write_names(&mut buffer, &["example.com."]);
+        buffer
+            .write_qname_uncompressed(&"example.com.".parse().unwrap())
+            .unwrap();
+        assert_eq!(buffer.buf.len(), 26);
+    }
+
+    #[test]
+    fn only_points_to_offsets_that_fit_in_14_bits() {
+        let mut buffer = VectorPacketBuffer::new();
+        for _ in 0..0x3fff {
+            buffer.write(0).unwrap();
+        }
+
+        // The first name starts at 0x3fff and can be pointed to, but its
+        // suffix `com.` starts past it.
+        write_names(&mut buffer, &["a.com.", "a.com.", "com."]);
+        assert_eq!(
+            &buffer.buf[0x3fff..],
+            b"\x01a\x03com\x00\xff\xff\x03com\x00"
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! RFC 1035 presentation (zone file) format for records and questions, e.g.
+//! `example.com. 300 IN MX 10 mail.example.com.`.
+//!
+//! Every record can be rendered with `Display` and parsed back with `FromStr`.
+//! Types we don't model, and the OPT pseudo-record, use the generic
+//! `TYPE1234 \# len hex` form of RFC 3597, which is also accepted as input for
+//! every other type. OPT has no presentation format of its own, so its payload
+//! size and flags are shown as the CLASS and TTL they are stored in.
+
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::dns_question::DnsQuestion;
+use super::dns_record::{format_generic_rdata, parse_generic_rdata, DnsRecord};
+use super::name::Name;
+use super::packet_buffer::PacketBuffer;
+use super::query_class::QueryClass;
+use super::query_type::QueryType;
+use super::vector_packet_buffer::VectorPacketBuffer;
+
+/// Renders a TXT character-string in quotes, escaping quotes and backslashes
+/// and writing non-printable bytes as `\DDD`.
+fn quote(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len() + 2);
+    result.push('"');
+    for &b in data {
+        match b {
+            b'"' | b'\\' => {
+                result.push('\\');
+                result.push(b as char);
+            }
+            0x20..=0x7E => result.push(b as char),
+            _ => result.push_str(&format!("\\{:03}", b)),
+        }
+    }
+    result.push('"');
+    result
+}
+
+impl DnsRecord {
+    /// Renders just the RDATA of the record in presentation format.
+    pub fn rdata_to_string(&self) -> String {
+        match *self {
+            DnsRecord::A { ref addr, .. } => addr.to_string(),
+            DnsRecord::AAAA { ref addr, .. } => addr.to_string(),
+            DnsRecord::NS { ref host, .. }
+            | DnsRecord::CNAME { ref host, .. }
+            | DnsRecord::PTR { ref host, .. } => host.to_string(),
+            DnsRecord::SOA {
+                ref m_name,
+                ref r_name,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+                ..
+            } => format!(
+                "{} {} {} {} {} {} {}",
+                m_name, r_name, serial, refresh, retry, expire, minimum
+            ),
+            DnsRecord::MX {
+                priority, ref host, ..
+            } => format!("{} {}", priority, host),
+            DnsRecord::TXT { ref data, .. } => {
+                if data.is_empty() {
+                    return "\"\"".to_string();
+                }
+                data.iter()
+                    .map(|txt| quote(txt))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            }
+            DnsRecord::SRV {
+                priority,
+                weight,
+                port,
+                ref host,
+                ..
+            } => format!("{} {} {} {}", priority, weight, port, host),
+            DnsRecord::UNKNOWN { ref data, .. } => format_generic_rdata(data),
+            DnsRecord::OPT { .. } => format_generic_rdata(&self.rdata().unwrap_or_default()),
+        }
+    }
+}
+
+impl fmt::Display for DnsRecord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {} {}",
+            self.domain(),
+            self.ttl(),
+            self.class(),
+            self.qtype(),
+            self.rdata_to_string()
+        )
+    }
+}
+
+/// Renders a question the way it appears in a zone file or `dig` output,
+/// e.g. `example.com. IN A`.
+impl fmt::Display for DnsQuestion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.name, self.qclass, self.qtype)
+    }
+}
+
+/// A whitespace separated field of a presentation format line. Quoted fields
+/// keep their escapes, which are only resolved for character-strings.
+struct Token<'a> {
+    text: &'a str,
+    quoted: bool,
+    pos: usize,
+}
+
+impl<'a> Token<'a> {
+    fn error(&self, msg: String) -> DnsError {
+        DnsError::new(DnsErrorKind::Syntax(msg), self.pos)
+    }
+
+    fn parse<T: FromStr>(&self, what: &str) -> Result<T> {
+        self.text
+            .parse::<T>()
+            .map_err(|_| self.error(format!("Invalid {} {}", what, self.text)))
+    }
+
+    /// Parses a domain name. Relative names aren't supported, so a missing
+    /// trailing dot is implied.
+    fn name(&self) -> Result<Name> {
+        if self.quoted {
+            return Err(self.error(format!("Invalid domain name {}", self.text)));
+        }
+        self.text.parse::<Name>().map_err(|e| DnsError {
+            offset: self.pos + e.offset,
+            ..e
+        })
+    }
+
+    /// Resolves the `\X` and `\DDD` escapes of a character-string.
+    fn character_string(&self) -> Result<Vec<u8>> {
+        let bytes = self.text.as_bytes();
+        let mut result = Vec::with_capacity(bytes.len());
+
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] != b'\\' {
+                result.push(bytes[i]);
+                i += 1;
+                continue;
+            }
+
+            let digits = bytes.get(i + 1..i + 4).unwrap_or(&[]);
+            if digits.len() == 3 && digits.iter().all(u8::is_ascii_digit) {
+                let value = digits
+                    .iter()
+                    .fold(0u32, |acc, d| acc * 10 + (d - b'0') as u32);
+                if value > 255 {
+                    return Err(self.error(format!("Invalid escape in {}", self.text)));
+                }
+                result.push(value as u8);
+                i += 4;
+            } else if let Some(&b) = bytes.get(i + 1) {
+                result.push(b);
+                i += 2;
+            } else {
+                return Err(self.error(format!("Dangling escape in {}", self.text)));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Splits a line into fields, honouring quotes and backslash escapes and
+/// dropping a trailing `;` comment.
+fn tokenize(s: &str) -> Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '"' => {
+                chars.next();
+                let mut escaped = false;
+                let end = loop {
+                    match chars.next() {
+                        Some((i, '"')) if !escaped => break i,
+                        Some((_, c)) => escaped = !escaped && c == '\\',
+                        None => {
+                            return Err(DnsError::new(
+                                DnsErrorKind::Syntax("Unterminated quoted string".to_string()),
+                                start,
+                            ))
+                        }
+                    }
+                };
+                tokens.push(Token {
+                    text: &s[start + 1..end],
+                    quoted: true,
+                    pos: start,
+                });
+            }
+            _ => {
+                let mut escaped = false;
+                let mut end = s.len();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !escaped && (c.is_whitespace() || c == ';' || c == '"') {
+                        end = i;
+                        break;
+                    }
+                    escaped = !escaped && c == '\\';
+                    chars.next();
+                }
+                tokens.push(Token {
+                    text: &s[start..end],
+                    quoted: false,
+                    pos: start,
+                });
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Builds a record from generic RDATA by running it through the wire format
+/// parser, so that `\#` works for every type.
+fn record_from_generic(
+    domain: &Name,
+    qtype: QueryType,
+    class: QueryClass,
+    ttl: u32,
+    rdata: &[u8],
+) -> Result<DnsRecord> {
+    let mut buffer = VectorPacketBuffer::new();
+    buffer.set_compression(false);
+    buffer.write_qname(domain)?;
+    buffer.write_u16(qtype.to_num())?;
+    buffer.write_u16(class.to_num())?;
+    buffer.write_u32(ttl)?;
+    buffer.write_u16(rdata.len() as u16)?;
+    for b in rdata {
+        buffer.write_u8(*b)?;
+    }
+
+    buffer.seek(0)?;
+    DnsRecord::read(&mut buffer)
+}
+
+impl FromStr for DnsRecord {
+    type Err = DnsError;
+
+    /// Parses `<name> [<ttl>] [<class>] <type> <rdata>`. The TTL defaults to
+    /// 0 and the class to IN, and they may appear in either order.
+    fn from_str(s: &str) -> Result<DnsRecord> {
+        let tokens = tokenize(s)?;
+        let missing =
+            |what: &str| DnsError::new(DnsErrorKind::Syntax(format!("Missing {}", what)), s.len());
+
+        let mut fields = tokens.iter();
+        let domain = fields
+            .next()
+            .ok_or_else(|| missing("domain name"))?
+            .name()?;
+
+        let mut ttl = None;
+        let mut class = None;
+        let qtype = loop {
+            let token = fields.next().ok_or_else(|| missing("record type"))?;
+            if ttl.is_none() && token.text.bytes().all(|b| b.is_ascii_digit()) {
+                ttl = Some(token.parse::<u32>("TTL")?);
+            } else if class.is_none() && token.text.parse::<QueryClass>().is_ok() {
+                class = Some(token.parse::<QueryClass>("class")?);
+            } else {
+                break token.parse::<QueryType>("record type")?;
+            }
+        };
+        let ttl = ttl.unwrap_or(0);
+        let class = class.unwrap_or(QueryClass::IN);
+
+        let rdata: Vec<&Token> = fields.collect();
+        if let Some(first) = rdata.first() {
+            if first.text == "\\#" && !first.quoted {
+                let text = rdata.iter().map(|t| t.text).collect::<Vec<_>>().join(" ");
+                let data =
+                    parse_generic_rdata(&text).map_err(|e| first.error(e.kind.to_string()))?;
+                return record_from_generic(&domain, qtype, class, ttl, &data);
+            }
+        }
+
+        // Every type but TXT has a fixed number of RDATA fields.
+        let field_count = match qtype {
+            QueryType::A | QueryType::AAAA | QueryType::NS | QueryType::CNAME | QueryType::PTR => 1,
+            QueryType::MX => 2,
+            QueryType::SRV => 4,
+            QueryType::SOA => 7,
+            _ => rdata.len(),
+        };
+        if let Some(extra) = rdata.get(field_count) {
+            return Err(extra.error(format!("Unexpected {}", extra.text)));
+        }
+        let field = |i: usize| rdata.get(i).copied().ok_or_else(|| missing("RDATA"));
+
+        let record = match qtype {
+            QueryType::A => DnsRecord::A {
+                domain,
+                addr: field(0)?.parse::<Ipv4Addr>("IPv4 address")?,
+                class,
+                ttl,
+            },
+            QueryType::AAAA => DnsRecord::AAAA {
+                domain,
+                addr: field(0)?.parse::<Ipv6Addr>("IPv6 address")?,
+                class,
+                ttl,
+            },
+            QueryType::NS => DnsRecord::NS {
+                domain,
+                host: field(0)?.name()?,
+                class,
+                ttl,
+            },
+            QueryType::CNAME => DnsRecord::CNAME {
+                domain,
+                host: field(0)?.name()?,
+                class,
+                ttl,
+            },
+            QueryType::PTR => DnsRecord::PTR {
+                domain,
+                host: field(0)?.name()?,
+                class,
+                ttl,
+            },
+            QueryType::SOA => DnsRecord::SOA {
+                domain,
+                m_name: field(0)?.name()?,
+                r_name: field(1)?.name()?,
+                serial: field(2)?.parse::<u32>("serial")?,
+                refresh: field(3)?.parse::<u32>("refresh")?,
+                retry: field(4)?.parse::<u32>("retry")?,
+                expire: field(5)?.parse::<u32>("expire")?,
+                minimum: field(6)?.parse::<u32>("minimum")?,
+                class,
+                ttl,
+            },
+            QueryType::MX => DnsRecord::MX {
+                domain,
+                priority: field(0)?.parse::<u16>("priority")?,
+                host: field(1)?.name()?,
+                class,
+                ttl,
+            },
+            QueryType::SRV => DnsRecord::SRV {
+                domain,
+                priority: field(0)?.parse::<u16>("priority")?,
+                weight: field(1)?.parse::<u16>("weight")?,
+                port: field(2)?.parse::<u16>("port")?,
+                host: field(3)?.name()?,
+                class,
+                ttl,
+            },
+            QueryType::TXT => {
+                field(0)?;
+                let mut data = Vec::new();
+                for token in &rdata {
+                    data.push(token.character_string()?);
+                }
+                DnsRecord::TXT {
+                    domain,
+                    data,
+                    class,
+                    ttl,
+                }
+            }
+            QueryType::OPT | QueryType::UNKNOWN(_) => {
+                return Err(field(0)?.error(format!(
+                    "{} records must use the generic \\# RDATA form",
+                    qtype
+                )));
+            }
+        };
+
+        Ok(record)
+    }
+}
+
+impl FromStr for DnsQuestion {
+    type Err = DnsError;
+
+    /// Parses `<name> [<class>] <type>`, with the class defaulting to IN.
+    fn from_str(s: &str) -> Result<DnsQuestion> {
+        let tokens = tokenize(s.trim_start_matches(';'))?;
+        let missing =
+            |what: &str| DnsError::new(DnsErrorKind::Syntax(format!("Missing {}", what)), s.len());
+
+        match tokens.len() {
+            2 => Ok(DnsQuestion::new(
+                tokens[0].name()?,
+                tokens[1].parse::<QueryType>("record type")?,
+            )),
+            3 => Ok(DnsQuestion::with_class(
+                tokens[0].name()?,
+                tokens[2].parse::<QueryType>("record type")?,
+                tokens[1].parse::<QueryClass>("class")?,
+            )),
+            0 => Err(missing("domain name")),
+            1 => Err(missing("record type")),
+            _ => Err(tokens[3].error(format!("Unexpected {}", tokens[3].text))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `text`, checks that it renders back to `text`, and that the
+    /// record survives a trip through the wire format.
+    fn round_trip(text: &str) -> DnsRecord {
+        let rec = text.parse::<DnsRecord>().unwrap();
+        assert_eq!(rec.to_string(), text);
+
+        let mut buffer = VectorPacketBuffer::new();
+        rec.write(&mut buffer).unwrap();
+        buffer.seek(0).unwrap();
+        assert_eq!(DnsRecord::read(&mut buffer).unwrap(), rec);
+
+        rec
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        round_trip("example.com. 300 IN A 192.0.2.1");
+        round_trip("example.com. 300 IN AAAA 2001:db8::1");
+        round_trip("example.com. 86400 IN NS ns1.example.com.");
+        round_trip("www.example.com. 300 IN CNAME example.com.");
+        round_trip("1.2.0.192.in-addr.arpa. 300 IN PTR example.com.");
+        round_trip(
+            "example.com. 3600 IN SOA ns1.example.com. admin.example.com. 2020010101 7200 900 1209600 300",
+        );
+        round_trip("example.com. 300 IN MX 10 mail.example.com.");
+        round_trip("_sip._tcp.example.com. 300 IN SRV 10 60 5060 sip.example.com.");
+        round_trip("example.com. 300 IN TXT \"v=spf1 -all\" \"second string\"");
+        round_trip("example.com. 300 CH TXT \"chaos\"");
+    }
+
+    #[test]
+    fn round_trips_txt_escapes() {
+        let rec = round_trip(r#"example.com. 300 IN TXT "say \"hi\"" "back\\slash" "\000\255;""#);
+        match rec {
+            DnsRecord::TXT { ref data, .. } => assert_eq!(
+                data,
+                &vec![
+                    b"say \"hi\"".to_vec(),
+                    b"back\\slash".to_vec(),
+                    vec![0, 255, b';'],
+                ]
+            ),
+            _ => panic!("Expected TXT, got {:?}", rec),
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_txt() {
+        let rec = round_trip("example.com. 300 IN TXT \"\"");
+        assert_eq!(rec.rdata().unwrap(), vec![0]);
+
+        // TXT RDATA without any strings is written, and shown, as a single
+        // empty string.
+        let rec: DnsRecord = "example.com. 300 IN TXT \\# 0".parse().unwrap();
+        assert_eq!(rec.to_string(), "example.com. 300 IN TXT \"\"");
+        assert_eq!(rec.rdata().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn round_trips_escaped_names() {
+        let rec = round_trip(r"a\.b.example.com. 300 IN CNAME \032space\\.example.com.");
+        assert_eq!(rec.domain().num_labels(), 3);
+        match rec {
+            DnsRecord::CNAME { ref host, .. } => {
+                assert_eq!(host.labels().next().unwrap(), b" space\\")
+            }
+            _ => panic!("Expected CNAME, got {:?}", rec),
+        }
+    }
+
+    #[test]
+    fn round_trips_unknown_types() {
+        let rec = round_trip("example.com. 300 IN TYPE999 \\# 3 010203");
+        assert_eq!(rec.qtype(), QueryType::UNKNOWN(999));
+        round_trip("example.com. 300 CLASS42 TYPE999 \\# 0");
+    }
+
+    #[test]
+    fn round_trips_opt() {
+        let rec = round_trip(". 32768 CLASS4096 OPT \\# 6 000a00020102");
+        match rec {
+            DnsRecord::OPT {
+                packet_len,
+                flags,
+                ref options,
+            } => {
+                assert_eq!(packet_len, 4096);
+                assert_eq!(flags, 0x8000);
+                assert_eq!(options.len(), 1);
+                assert_eq!(options[0].code, 10);
+                assert_eq!(options[0].data, vec![1, 2]);
+            }
+            _ => panic!("Expected OPT, got {:?}", rec),
+        }
+    }
+
+    #[test]
+    fn accepts_generic_rdata_for_known_types() {
+        let rec: DnsRecord = "example.com. 300 IN A \\# 4 c0000201".parse().unwrap();
+        assert_eq!(rec, "example.com. 300 IN A 192.0.2.1".parse().unwrap());
+
+        let rec: DnsRecord = "example.com. 300 IN MX \\# 6 000A 02 6D78 00"
+            .parse()
+            .unwrap();
+        assert_eq!(rec.to_string(), "example.com. 300 IN MX 10 mx.");
+
+        // The length has to match the data, and the data the type.
+        assert!("example.com. 300 IN A \\# 4 C00002"
+            .parse::<DnsRecord>()
+            .is_err());
+        assert!("example.com. 300 IN A \\# 3 C00002"
+            .parse::<DnsRecord>()
+            .is_err());
+    }
+
+    #[test]
+    fn fills_in_defaults() {
+        let rec: DnsRecord = "example.com. A 192.0.2.1".parse().unwrap();
+        assert_eq!(rec.to_string(), "example.com. 0 IN A 192.0.2.1");
+
+        let rec: DnsRecord = "example.com IN 300 A 192.0.2.1 ; comment".parse().unwrap();
+        assert_eq!(rec.to_string(), "example.com. 300 IN A 192.0.2.1");
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        for text in &[
+            "",
+            "example.com.",
+            "example.com. 300 IN",
+            "example.com. 300 IN A",
+            "example.com. 300 IN A 192.0.2.1 192.0.2.2",
+            "example.com. 300 IN A 192.0.2",
+            "example.com. 300 IN MX mail.example.com.",
+            "example.com. 300 IN TXT \"unterminated",
+            "example.com. 300 IN TXT \"\\256\"",
+            "example.com. 300 IN TYPE999 010203",
+            "example.com. 300 IN OPT 4096",
+        ] {
+            assert!(text.parse::<DnsRecord>().is_err(), "{:?} parsed", text);
+        }
+    }
+
+    #[test]
+    fn round_trips_questions() {
+        for text in &[
+            "example.com. IN A",
+            "example.com. CH TXT",
+            "example.com. IN TYPE999",
+        ] {
+            let question = text.parse::<DnsQuestion>().unwrap();
+            assert_eq!(question.to_string(), *text);
+        }
+
+        let question: DnsQuestion = "example.com AAAA".parse().unwrap();
+        assert_eq!(question.to_string(), "example.com. IN AAAA");
+    }
+}
@@ -0,0 +1,74 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::dns_error::DnsError;
+
+#[derive(PartialEq, Eq, Debug, Clone, Hash, Copy, PartialOrd, Ord)]
+pub enum QueryClass {
+    UNKNOWN(u16),
+    IN,   // 1
+    CH,   // 3
+    HS,   // 4
+    NONE, // 254
+    ANY,  // 255
+}
+
+impl QueryClass {
+    pub fn to_num(self) -> u16 {
+        match self {
+            QueryClass::UNKNOWN(x) => x,
+            QueryClass::IN => 1,
+            QueryClass::CH => 3,
+            QueryClass::HS => 4,
+            QueryClass::NONE => 254,
+            QueryClass::ANY => 255,
+        }
+    }
+
+    pub fn from_num(num: u16) -> QueryClass {
+        match num {
+            1 => QueryClass::IN,
+            3 => QueryClass::CH,
+            4 => QueryClass::HS,
+            254 => QueryClass::NONE,
+            255 => QueryClass::ANY,
+            _ => QueryClass::UNKNOWN(num),
+        }
+    }
+}
+
+/// Formats the class mnemonic, or `CLASS1234` for classes without one as
+/// described in RFC 3597.
+impl fmt::Display for QueryClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryClass::UNKNOWN(x) => write!(f, "CLASS{}", x),
+            QueryClass::IN => write!(f, "IN"),
+            QueryClass::CH => write!(f, "CH"),
+            QueryClass::HS => write!(f, "HS"),
+            QueryClass::NONE => write!(f, "NONE"),
+            QueryClass::ANY => write!(f, "ANY"),
+        }
+    }
+}
+
+impl FromStr for QueryClass {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<QueryClass, DnsError> {
+        let upper = s.to_ascii_uppercase();
+        let qclass = match upper.as_str() {
+            "IN" => QueryClass::IN,
+            "CH" | "CHAOS" => QueryClass::CH,
+            "HS" | "HESIOD" => QueryClass::HS,
+            "NONE" => QueryClass::NONE,
+            "ANY" | "*" => QueryClass::ANY,
+            _ => match upper.strip_prefix("CLASS").map(|num| num.parse::<u16>()) {
+                Some(Ok(num)) => QueryClass::from_num(num),
+                _ => return Err(DnsError::syntax(format!("Unknown record class {}", s))),
+            },
+        };
+
+        Ok(qclass)
+    }
+}
@@ -1,22 +1,37 @@
+use std::fmt;
+use std::str::FromStr;
+
+use super::dns_error::DnsError;
+
 #[derive(PartialEq, Eq, Debug, Clone, Hash, Copy)]
 pub enum QueryType {
     UNKNOWN(u16),
     A,     // 1
     NS,    // 2
     CNAME, // 5
+    SOA,   // 6
+    PTR,   // 12
     MX,    // 15
+    TXT,   // 16
     AAAA,  // 28
+    SRV,   // 33
+    OPT,   // 41
 }
 
 impl QueryType {
-    pub fn to_num(&self) -> u16 {
-        match *self {
+    pub fn to_num(self) -> u16 {
+        match self {
             QueryType::UNKNOWN(x) => x,
             QueryType::A => 1,
             QueryType::NS => 2,
             QueryType::CNAME => 5,
+            QueryType::SOA => 6,
+            QueryType::PTR => 12,
             QueryType::MX => 15,
+            QueryType::TXT => 16,
             QueryType::AAAA => 28,
+            QueryType::SRV => 33,
+            QueryType::OPT => 41,
         }
     }
 
@@ -25,9 +40,60 @@ impl QueryType {
             1 => QueryType::A,
             2 => QueryType::NS,
             5 => QueryType::CNAME,
+            6 => QueryType::SOA,
+            12 => QueryType::PTR,
             15 => QueryType::MX,
+            16 => QueryType::TXT,
             28 => QueryType::AAAA,
+            33 => QueryType::SRV,
+            41 => QueryType::OPT,
             _ => QueryType::UNKNOWN(num),
         }
     }
 }
+
+/// Formats the type mnemonic, or `TYPE1234` for types we don't model as
+/// described in RFC 3597.
+impl fmt::Display for QueryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            QueryType::UNKNOWN(x) => write!(f, "TYPE{}", x),
+            QueryType::A => write!(f, "A"),
+            QueryType::NS => write!(f, "NS"),
+            QueryType::CNAME => write!(f, "CNAME"),
+            QueryType::SOA => write!(f, "SOA"),
+            QueryType::PTR => write!(f, "PTR"),
+            QueryType::MX => write!(f, "MX"),
+            QueryType::TXT => write!(f, "TXT"),
+            QueryType::AAAA => write!(f, "AAAA"),
+            QueryType::SRV => write!(f, "SRV"),
+            QueryType::OPT => write!(f, "OPT"),
+        }
+    }
+}
+
+impl FromStr for QueryType {
+    type Err = DnsError;
+
+    fn from_str(s: &str) -> Result<QueryType, DnsError> {
+        let upper = s.to_ascii_uppercase();
+        let qtype = match upper.as_str() {
+            "A" => QueryType::A,
+            "NS" => QueryType::NS,
+            "CNAME" => QueryType::CNAME,
+            "SOA" => QueryType::SOA,
+            "PTR" => QueryType::PTR,
+            "MX" => QueryType::MX,
+            "TXT" => QueryType::TXT,
+            "AAAA" => QueryType::AAAA,
+            "SRV" => QueryType::SRV,
+            "OPT" => QueryType::OPT,
+            _ => match upper.strip_prefix("TYPE").map(|num| num.parse::<u16>()) {
+                Some(Ok(num)) => QueryType::from_num(num),
+                _ => return Err(DnsError::syntax(format!("Unknown record type {}", s))),
+            },
+        };
+
+        Ok(qtype)
+    }
+}
@@ -1,22 +1,117 @@
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+use std::fmt;
+
+/// A response code from the IANA "DNS RCODEs" registry. Codes up to 15 fit in
+/// the header; larger ones need the extended RCODE bits of an OPT record, so
+/// the full 12 bit value is only available through `DnsPacket::rescode`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum ResultCode {
-    NOERROR = 0,
-    FORMERR = 1,
-    SERVFAIL = 2,
-    NXDOMAIN = 3,
-    NOTIMP = 4,
-    REFUSED = 5,
+    UNKNOWN(u16),
+    NOERROR,   // 0
+    FORMERR,   // 1
+    SERVFAIL,  // 2
+    NXDOMAIN,  // 3
+    NOTIMP,    // 4
+    REFUSED,   // 5
+    YXDOMAIN,  // 6, RFC 2136
+    YXRRSET,   // 7, RFC 2136
+    NXRRSET,   // 8, RFC 2136
+    NOTAUTH,   // 9, RFC 2136 and RFC 8945
+    NOTZONE,   // 10, RFC 2136
+    DSOTYPENI, // 11, RFC 8490
+    BADVERS,   // 16, RFC 6891. Also BADSIG in TSIG records.
+    BADKEY,    // 17, RFC 8945
+    BADTIME,   // 18, RFC 8945
+    BADMODE,   // 19, RFC 2930
+    BADNAME,   // 20, RFC 2930
+    BADALG,    // 21, RFC 2930
+    BADTRUNC,  // 22, RFC 8945
+    BADCOOKIE, // 23, RFC 7873
 }
 
 impl ResultCode {
-    pub fn from_num(num: u8) -> ResultCode {
+    pub fn to_num(self) -> u16 {
+        match self {
+            ResultCode::UNKNOWN(x) => x,
+            ResultCode::NOERROR => 0,
+            ResultCode::FORMERR => 1,
+            ResultCode::SERVFAIL => 2,
+            ResultCode::NXDOMAIN => 3,
+            ResultCode::NOTIMP => 4,
+            ResultCode::REFUSED => 5,
+            ResultCode::YXDOMAIN => 6,
+            ResultCode::YXRRSET => 7,
+            ResultCode::NXRRSET => 8,
+            ResultCode::NOTAUTH => 9,
+            ResultCode::NOTZONE => 10,
+            ResultCode::DSOTYPENI => 11,
+            ResultCode::BADVERS => 16,
+            ResultCode::BADKEY => 17,
+            ResultCode::BADTIME => 18,
+            ResultCode::BADMODE => 19,
+            ResultCode::BADNAME => 20,
+            ResultCode::BADALG => 21,
+            ResultCode::BADTRUNC => 22,
+            ResultCode::BADCOOKIE => 23,
+        }
+    }
+
+    pub fn from_num(num: u16) -> ResultCode {
         match num {
+            0 => ResultCode::NOERROR,
             1 => ResultCode::FORMERR,
             2 => ResultCode::SERVFAIL,
             3 => ResultCode::NXDOMAIN,
             4 => ResultCode::NOTIMP,
             5 => ResultCode::REFUSED,
-            0 | _ => ResultCode::NOERROR,
+            6 => ResultCode::YXDOMAIN,
+            7 => ResultCode::YXRRSET,
+            8 => ResultCode::NXRRSET,
+            9 => ResultCode::NOTAUTH,
+            10 => ResultCode::NOTZONE,
+            11 => ResultCode::DSOTYPENI,
+            16 => ResultCode::BADVERS,
+            17 => ResultCode::BADKEY,
+            18 => ResultCode::BADTIME,
+            19 => ResultCode::BADMODE,
+            20 => ResultCode::BADNAME,
+            21 => ResultCode::BADALG,
+            22 => ResultCode::BADTRUNC,
+            23 => ResultCode::BADCOOKIE,
+            _ => ResultCode::UNKNOWN(num),
+        }
+    }
+
+    /// Whether the code needs the extended RCODE bits of an OPT record, i.e.
+    /// doesn't fit in the 4 bits of the header.
+    pub fn is_extended(self) -> bool {
+        self.to_num() > 0x0F
+    }
+}
+
+impl fmt::Display for ResultCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ResultCode::UNKNOWN(x) => write!(f, "RCODE{}", x),
+            ResultCode::NOERROR => write!(f, "NOERROR"),
+            ResultCode::FORMERR => write!(f, "FORMERR"),
+            ResultCode::SERVFAIL => write!(f, "SERVFAIL"),
+            ResultCode::NXDOMAIN => write!(f, "NXDOMAIN"),
+            ResultCode::NOTIMP => write!(f, "NOTIMP"),
+            ResultCode::REFUSED => write!(f, "REFUSED"),
+            ResultCode::YXDOMAIN => write!(f, "YXDOMAIN"),
+            ResultCode::YXRRSET => write!(f, "YXRRSET"),
+            ResultCode::NXRRSET => write!(f, "NXRRSET"),
+            ResultCode::NOTAUTH => write!(f, "NOTAUTH"),
+            ResultCode::NOTZONE => write!(f, "NOTZONE"),
+            ResultCode::DSOTYPENI => write!(f, "DSOTYPENI"),
+            ResultCode::BADVERS => write!(f, "BADVERS"),
+            ResultCode::BADKEY => write!(f, "BADKEY"),
+            ResultCode::BADTIME => write!(f, "BADTIME"),
+            ResultCode::BADMODE => write!(f, "BADMODE"),
+            ResultCode::BADNAME => write!(f, "BADNAME"),
+            ResultCode::BADALG => write!(f, "BADALG"),
+            ResultCode::BADTRUNC => write!(f, "BADTRUNC"),
+            ResultCode::BADCOOKIE => write!(f, "BADCOOKIE"),
         }
     }
 }
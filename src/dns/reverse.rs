@@ -0,0 +1,222 @@
+//! Helpers for mapping IP addresses to and from the names used for reverse
+//! (PTR) lookups, as described in RFC 1035 section 3.5 and RFC 3596 section 2.5.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str;
+
+use super::name::Name;
+
+const IPV4_SUFFIX: [&str; 2] = ["in-addr", "arpa"];
+const IPV6_SUFFIX: [&str; 2] = ["ip6", "arpa"];
+
+/// Builds a reverse name from its address labels, which are always short
+/// enough to make a valid name.
+fn reverse_name(labels: Vec<String>, suffix: [&str; 2]) -> Name {
+    let labels = labels
+        .iter()
+        .map(String::as_str)
+        .chain(suffix.iter().copied());
+    Name::from_labels(labels).expect("reverse names are always valid")
+}
+
+/// Returns the labels of `name` in front of `suffix`, if it ends with it.
+fn strip_suffix<'a>(name: &'a Name, suffix: [&str; 2]) -> Option<Vec<&'a [u8]>> {
+    let labels: Vec<&[u8]> = name.labels().collect();
+    if labels.len() < suffix.len() {
+        return None;
+    }
+
+    let (prefix, tail) = labels.split_at(labels.len() - suffix.len());
+    let matches = tail
+        .iter()
+        .zip(suffix.iter())
+        .all(|(label, expected)| label.eq_ignore_ascii_case(expected.as_bytes()));
+
+    if matches {
+        Some(prefix.to_vec())
+    } else {
+        None
+    }
+}
+
+/// Turns `192.0.2.1` into `1.2.0.192.in-addr.arpa.`.
+pub fn ipv4_to_reverse_name(addr: Ipv4Addr) -> Name {
+    let labels = addr.octets().iter().rev().map(|o| o.to_string()).collect();
+    reverse_name(labels, IPV4_SUFFIX)
+}
+
+/// Turns an IPv6 address into its 32 reversed nibbles under `ip6.arpa`, e.g.
+/// `2001:db8::1` becomes `1.0.0.0. ... .8.b.d.0.1.0.0.2.ip6.arpa.`.
+pub fn ipv6_to_reverse_name(addr: Ipv6Addr) -> Name {
+    let mut labels = Vec::with_capacity(32);
+    for octet in addr.octets().iter().rev() {
+        labels.push(format!("{:x}", octet & 0x0F));
+        labels.push(format!("{:x}", octet >> 4));
+    }
+    reverse_name(labels, IPV6_SUFFIX)
+}
+
+pub fn ip_to_reverse_name(addr: IpAddr) -> Name {
+    match addr {
+        IpAddr::V4(addr) => ipv4_to_reverse_name(addr),
+        IpAddr::V6(addr) => ipv6_to_reverse_name(addr),
+    }
+}
+
+/// Parses a full `in-addr.arpa` name back into an address. Returns `None` for
+/// anything that isn't exactly four decimal octets under `in-addr.arpa`.
+/// Octets with leading zeros, like `001`, are rejected too, so that each
+/// address has only the one name `ipv4_to_reverse_name` gives it.
+pub fn reverse_name_to_ipv4(name: &Name) -> Option<Ipv4Addr> {
+    let prefix = strip_suffix(name, IPV4_SUFFIX)?;
+    if prefix.len() != 4 {
+        return None;
+    }
+
+    let mut octets = [0u8; 4];
+    for (i, label) in prefix.iter().enumerate() {
+        if label.len() > 3 || !label.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        if label.len() > 1 && label[0] == b'0' {
+            return None;
+        }
+        octets[3 - i] = str::from_utf8(label).ok()?.parse::<u8>().ok()?;
+    }
+
+    Some(Ipv4Addr::from(octets))
+}
+
+/// Parses a full `ip6.arpa` name back into an address. Returns `None` unless
+/// the name holds exactly 32 single hex digit labels under `ip6.arpa`.
+pub fn reverse_name_to_ipv6(name: &Name) -> Option<Ipv6Addr> {
+    let prefix = strip_suffix(name, IPV6_SUFFIX)?;
+    if prefix.len() != 32 {
+        return None;
+    }
+
+    let mut octets = [0u8; 16];
+    for (count, label) in prefix.iter().enumerate() {
+        if label.len() != 1 {
+            return None;
+        }
+        let nibble = (label[0] as char).to_digit(16)? as u8;
+
+        // Nibbles come least significant first, so the first label is the low
+        // half of the last octet.
+        let idx = 15 - count / 2;
+        if count % 2 == 0 {
+            octets[idx] |= nibble;
+        } else {
+            octets[idx] |= nibble << 4;
+        }
+    }
+
+    Some(Ipv6Addr::from(octets))
+}
+
+pub fn reverse_name_to_ip(name: &Name) -> Option<IpAddr> {
+    reverse_name_to_ipv4(name)
+        .map(IpAddr::V4)
+        .or_else(|| reverse_name_to_ipv6(name).map(IpAddr::V6))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(s: &str) -> Name {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn converts_ipv4_both_ways() {
+        let addr = Ipv4Addr::new(192, 0, 2, 1);
+        let reverse = ipv4_to_reverse_name(addr);
+        assert_eq!(reverse.to_string(), "1.2.0.192.in-addr.arpa.");
+        assert_eq!(reverse_name_to_ipv4(&reverse), Some(addr));
+        assert_eq!(reverse_name_to_ip(&reverse), Some(IpAddr::V4(addr)));
+
+        for &addr in &[
+            Ipv4Addr::UNSPECIFIED,
+            Ipv4Addr::BROADCAST,
+            Ipv4Addr::new(10, 0, 100, 9),
+        ] {
+            assert_eq!(
+                reverse_name_to_ipv4(&ipv4_to_reverse_name(addr)),
+                Some(addr)
+            );
+        }
+        assert_eq!(
+            reverse_name_to_ipv4(&name("1.2.0.192.IN-ADDR.ARPA")),
+            Some(addr)
+        );
+    }
+
+    #[test]
+    fn converts_ipv6_both_ways() {
+        let addr: Ipv6Addr = "2001:db8::1".parse().unwrap();
+        let reverse = ipv6_to_reverse_name(addr);
+        assert_eq!(
+            reverse.to_string(),
+            "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa."
+        );
+        assert_eq!(reverse_name_to_ipv6(&reverse), Some(addr));
+        assert_eq!(reverse_name_to_ip(&reverse), Some(IpAddr::V6(addr)));
+
+        for s in &[
+            "::",
+            "::1",
+            "fe80::abcd:ef01:2345:6789",
+            "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff",
+        ] {
+            let addr: Ipv6Addr = s.parse().unwrap();
+            assert_eq!(
+                reverse_name_to_ipv6(&ipv6_to_reverse_name(addr)),
+                Some(addr)
+            );
+        }
+
+        let upper =
+            name("1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.B.D.0.1.0.0.2.IP6.ARPA");
+        assert_eq!(reverse_name_to_ipv6(&upper), Some(addr));
+    }
+
+    #[test]
+    fn rejects_malformed_ipv4_names() {
+        for s in &[
+            "2.0.192.in-addr.arpa",
+            "0.1.2.0.192.in-addr.arpa",
+            "256.2.0.192.in-addr.arpa",
+            "001.2.0.192.in-addr.arpa",
+            "1.02.0.192.in-addr.arpa",
+            "1.2.00.192.in-addr.arpa",
+            "1.2.0.0192.in-addr.arpa",
+            "a.2.0.192.in-addr.arpa",
+            "-1.2.0.192.in-addr.arpa",
+            "1.2.0.192.ip6.arpa",
+            "1.2.0.192.in-addr.example",
+            "in-addr.arpa",
+            "arpa",
+        ] {
+            assert_eq!(reverse_name_to_ipv4(&name(s)), None, "{}", s);
+            assert_eq!(reverse_name_to_ip(&name(s)), None, "{}", s);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_ipv6_names() {
+        let good = "1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2";
+        for s in &[
+            format!("{}.ip6.arpa", &good[2..]),
+            format!("0.{}.ip6.arpa", good),
+            format!("10.{}.ip6.arpa", &good[2..]),
+            format!("g.{}.ip6.arpa", &good[2..]),
+            format!("{}.in-addr.arpa", good),
+            "ip6.arpa".to_string(),
+        ] {
+            assert_eq!(reverse_name_to_ipv6(&name(s)), None, "{}", s);
+            assert_eq!(reverse_name_to_ip(&name(s)), None, "{}", s);
+        }
+    }
+}
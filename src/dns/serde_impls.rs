@@ -0,0 +1,533 @@
+//! Serde support for packets, enabled with the `serde` cargo feature.
+//!
+//! The layout follows RFC 8427 (DNS in JSON) where practical: header fields
+//! use the RFC's member names (`ID`, `QR`, `RCODE`, ...), the sections are
+//! `questionRRs`, `answerRRs`, `authorityRRs` and `additionalRRs`, and every
+//! record carries `NAME`, `TYPE`, `TYPEname`, `CLASS`, `CLASSname` and `TTL`.
+//! Record data is given in presentation format under `rdata<TYPE>` (e.g.
+//! `"rdataMX": "10 mail.example.com."`), or as `RDATAHEX` for types we don't
+//! model and for OPT.
+
+use std::collections::BTreeMap;
+
+use serde::de::Error as DeError;
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::dns_header::DnsHeader;
+use super::dns_packet::DnsPacket;
+use super::dns_question::DnsQuestion;
+use super::dns_record::{format_generic_rdata, DnsRecord};
+use super::name::Name;
+use super::opcode::Opcode;
+use super::query_class::QueryClass;
+use super::query_type::QueryType;
+use super::result_code::ResultCode;
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+/// Names are given in presentation format, e.g. `"www.example.com."`.
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Name, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse::<Name>().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct HeaderRepr {
+    #[serde(rename = "ID")]
+    id: u16,
+    #[serde(rename = "QR")]
+    qr: bool,
+    #[serde(rename = "Opcode")]
+    opcode: u8,
+    #[serde(rename = "AA")]
+    aa: bool,
+    #[serde(rename = "TC")]
+    tc: bool,
+    #[serde(rename = "RD")]
+    rd: bool,
+    #[serde(rename = "RA")]
+    ra: bool,
+    #[serde(rename = "Z", default)]
+    z: bool,
+    #[serde(rename = "AD")]
+    ad: bool,
+    #[serde(rename = "CD")]
+    cd: bool,
+    #[serde(rename = "RCODE")]
+    rcode: u16,
+    #[serde(rename = "QDCOUNT", default)]
+    qdcount: u16,
+    #[serde(rename = "ANCOUNT", default)]
+    ancount: u16,
+    #[serde(rename = "NSCOUNT", default)]
+    nscount: u16,
+    #[serde(rename = "ARCOUNT", default)]
+    arcount: u16,
+}
+
+impl From<&DnsHeader> for HeaderRepr {
+    fn from(header: &DnsHeader) -> HeaderRepr {
+        HeaderRepr {
+            id: header.id,
+            qr: header.response,
+            opcode: header.opcode.to_num(),
+            aa: header.authoritative_answer,
+            tc: header.truncated_message,
+            rd: header.recursion_desired,
+            ra: header.recursion_available,
+            z: header.z,
+            ad: header.authed_data,
+            cd: header.checking_disabled,
+            rcode: header.rescode.to_num(),
+            qdcount: header.questions,
+            ancount: header.answers,
+            nscount: header.authoritative_entries,
+            arcount: header.resource_entries,
+        }
+    }
+}
+
+impl From<HeaderRepr> for DnsHeader {
+    fn from(repr: HeaderRepr) -> DnsHeader {
+        DnsHeader {
+            id: repr.id,
+            recursion_desired: repr.rd,
+            truncated_message: repr.tc,
+            authoritative_answer: repr.aa,
+            opcode: Opcode::from_num(repr.opcode),
+            response: repr.qr,
+            rescode: ResultCode::from_num(repr.rcode),
+            checking_disabled: repr.cd,
+            authed_data: repr.ad,
+            z: repr.z,
+            recursion_available: repr.ra,
+            questions: repr.qdcount,
+            answers: repr.ancount,
+            authoritative_entries: repr.nscount,
+            resource_entries: repr.arcount,
+        }
+    }
+}
+
+impl Serialize for DnsHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HeaderRepr::from(self).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DnsHeader, D::Error> {
+        HeaderRepr::deserialize(deserializer).map(DnsHeader::from)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct QuestionRepr {
+    #[serde(rename = "QNAME")]
+    name: Name,
+    #[serde(rename = "QTYPE", default, skip_serializing_if = "Option::is_none")]
+    qtype: Option<u16>,
+    #[serde(rename = "QTYPEname", default, skip_serializing_if = "Option::is_none")]
+    qtype_name: Option<String>,
+    #[serde(rename = "QCLASS", default, skip_serializing_if = "Option::is_none")]
+    qclass: Option<u16>,
+    #[serde(
+        rename = "QCLASSname",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    qclass_name: Option<String>,
+}
+
+impl Serialize for DnsQuestion {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        QuestionRepr {
+            name: self.name.clone(),
+            qtype: Some(self.qtype.to_num()),
+            qtype_name: Some(self.qtype.to_string()),
+            qclass: Some(self.qclass.to_num()),
+            qclass_name: Some(self.qclass.to_string()),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsQuestion {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DnsQuestion, D::Error> {
+        let repr = QuestionRepr::deserialize(deserializer)?;
+
+        let qtype = match (repr.qtype, repr.qtype_name) {
+            (Some(num), _) => QueryType::from_num(num),
+            (None, Some(name)) => name.parse::<QueryType>().map_err(D::Error::custom)?,
+            (None, None) => return Err(D::Error::missing_field("QTYPE")),
+        };
+        let qclass = match (repr.qclass, repr.qclass_name) {
+            (Some(num), _) => QueryClass::from_num(num),
+            (None, Some(name)) => name.parse::<QueryClass>().map_err(D::Error::custom)?,
+            (None, None) => QueryClass::IN,
+        };
+
+        Ok(DnsQuestion::with_class(repr.name, qtype, qclass))
+    }
+}
+
+impl Serialize for DnsRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let qtype = self.qtype();
+        let class = self.class();
+
+        let mut map = serializer.serialize_map(None)?;
+        map.serialize_entry("NAME", self.domain())?;
+        map.serialize_entry("TYPE", &qtype.to_num())?;
+        map.serialize_entry("TYPEname", &qtype.to_string())?;
+        map.serialize_entry("CLASS", &class.to_num())?;
+        if let DnsRecord::OPT { .. } = *self {
+            // The class of OPT is a payload size, not a class.
+        } else {
+            map.serialize_entry("CLASSname", &class.to_string())?;
+        }
+        map.serialize_entry("TTL", &self.ttl())?;
+
+        match *self {
+            DnsRecord::UNKNOWN { ref data, .. } => {
+                map.serialize_entry("RDATAHEX", &to_hex(data))?;
+            }
+            DnsRecord::OPT { .. } => {
+                let data = self.rdata().map_err(serde::ser::Error::custom)?;
+                map.serialize_entry("RDATAHEX", &to_hex(&data))?;
+            }
+            _ => {
+                map.serialize_entry(&format!("rdata{}", qtype), &self.rdata_to_string())?;
+            }
+        }
+
+        map.end()
+    }
+}
+
+/// A member of a record object, which is either a number or a string.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RecordValue {
+    Number(u64),
+    Text(String),
+}
+
+impl<'de> Deserialize<'de> for DnsRecord {
+    /// Rebuilds the record in presentation format and parses that, so that
+    /// JSON input is validated exactly like zone file input.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DnsRecord, D::Error> {
+        let members = BTreeMap::<String, RecordValue>::deserialize(deserializer)?;
+
+        let number = |key: &'static str| match members.get(key) {
+            Some(RecordValue::Number(num)) => Ok(Some(*num)),
+            Some(RecordValue::Text(_)) => {
+                Err(D::Error::custom(format!("{} must be a number", key)))
+            }
+            None => Ok(None),
+        };
+        let text = |key: &str| match members.get(key) {
+            Some(RecordValue::Text(text)) => Ok(Some(text.clone())),
+            Some(RecordValue::Number(_)) => {
+                Err(D::Error::custom(format!("{} must be a string", key)))
+            }
+            None => Ok(None),
+        };
+
+        let name = text("NAME")?.ok_or_else(|| D::Error::missing_field("NAME"))?;
+        let u16_number = |key: &'static str| match number(key)? {
+            Some(num) if num > u16::MAX as u64 => Err(D::Error::custom(format!(
+                "{} must be at most {}",
+                key,
+                u16::MAX
+            ))),
+            num => Ok(num.map(|num| num as u16)),
+        };
+
+        let qtype = match (u16_number("TYPE")?, text("TYPEname")?) {
+            (Some(num), _) => QueryType::from_num(num),
+            (None, Some(name)) => name.parse::<QueryType>().map_err(D::Error::custom)?,
+            (None, None) => return Err(D::Error::missing_field("TYPE")),
+        };
+        let class = match (u16_number("CLASS")?, text("CLASSname")?) {
+            (Some(num), _) => num,
+            (None, Some(name)) => name
+                .parse::<QueryClass>()
+                .map_err(D::Error::custom)?
+                .to_num(),
+            (None, None) => QueryClass::IN.to_num(),
+        };
+        let ttl = number("TTL")?.unwrap_or(0);
+
+        let rdata = match text("RDATAHEX")? {
+            Some(hex) => {
+                if hex.len() % 2 != 0 || !hex.is_ascii() {
+                    return Err(D::Error::custom("RDATAHEX must hold whole bytes"));
+                }
+                let mut data = Vec::with_capacity(hex.len() / 2);
+                for i in (0..hex.len()).step_by(2) {
+                    let byte = u8::from_str_radix(&hex[i..i + 2], 16).map_err(D::Error::custom)?;
+                    data.push(byte);
+                }
+                format_generic_rdata(&data)
+            }
+            None => text(&format!("rdata{}", qtype))?
+                .ok_or_else(|| D::Error::custom(format!("missing field `rdata{}`", qtype)))?,
+        };
+
+        // The class is always given by number, since OPT stores its payload
+        // size there.
+        let line = format!(
+            "{} {} {} {} {}",
+            name,
+            ttl,
+            QueryClass::UNKNOWN(class),
+            qtype,
+            rdata
+        );
+        line.parse::<DnsRecord>().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Serialize)]
+struct PacketRef<'a> {
+    #[serde(flatten)]
+    header: HeaderRepr,
+    #[serde(rename = "questionRRs")]
+    questions: &'a [DnsQuestion],
+    #[serde(rename = "answerRRs")]
+    answers: &'a [DnsRecord],
+    #[serde(rename = "authorityRRs")]
+    authorities: &'a [DnsRecord],
+    #[serde(rename = "additionalRRs")]
+    resources: &'a [DnsRecord],
+}
+
+#[derive(Deserialize)]
+struct PacketRepr {
+    #[serde(flatten)]
+    header: DnsHeader,
+    #[serde(rename = "questionRRs", default)]
+    questions: Vec<DnsQuestion>,
+    #[serde(rename = "answerRRs", default)]
+    answers: Vec<DnsRecord>,
+    #[serde(rename = "authorityRRs", default)]
+    authorities: Vec<DnsRecord>,
+    #[serde(rename = "additionalRRs", default)]
+    resources: Vec<DnsRecord>,
+}
+
+impl Serialize for DnsPacket {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // The counts follow the sections, as they do when writing a packet,
+        // rather than whatever the header held when it was last written.
+        let mut header = HeaderRepr::from(&self.header);
+        header.qdcount = self.questions.len() as u16;
+        header.ancount = self.answers.len() as u16;
+        header.nscount = self.authorities.len() as u16;
+        header.arcount = self.resources.len() as u16;
+
+        PacketRef {
+            header,
+            questions: &self.questions,
+            answers: &self.answers,
+            authorities: &self.authorities,
+            resources: &self.resources,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DnsPacket {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<DnsPacket, D::Error> {
+        let repr = PacketRepr::deserialize(deserializer)?;
+
+        let mut packet = DnsPacket::new();
+        packet.header = repr.header;
+        packet.questions = repr.questions;
+        packet.answers = repr.answers;
+        packet.authorities = repr.authorities;
+        packet.resources = repr.resources;
+
+        // The counts follow the sections, as they do when writing a packet.
+        packet.header.questions = packet.questions.len() as u16;
+        packet.header.answers = packet.answers.len() as u16;
+        packet.header.authoritative_entries = packet.authorities.len() as u16;
+        packet.header.resource_entries = packet.resources.len() as u16;
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Checks that `text` survives a trip through JSON, and returns the JSON.
+    fn round_trip(text: &str) -> serde_json::Value {
+        let rec = text.parse::<DnsRecord>().unwrap();
+        let value = serde_json::to_value(&rec).unwrap();
+        let parsed: DnsRecord = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(parsed, rec);
+        assert_eq!(parsed.to_string(), text);
+        value
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        round_trip("example.com. 300 IN A 192.0.2.1");
+        round_trip("example.com. 300 IN AAAA 2001:db8::1");
+        round_trip("example.com. 86400 IN NS ns1.example.com.");
+        round_trip("www.example.com. 300 IN CNAME example.com.");
+        round_trip("1.2.0.192.in-addr.arpa. 300 IN PTR example.com.");
+        round_trip(
+            "example.com. 3600 IN SOA ns1.example.com. admin.example.com. 1 7200 900 1209600 300",
+        );
+        round_trip("example.com. 300 IN MX 10 mail.example.com.");
+        round_trip("_sip._tcp.example.com. 300 IN SRV 10 60 5060 sip.example.com.");
+        round_trip("example.com. 300 CH TXT \"chaos\"");
+    }
+
+    #[test]
+    fn uses_rfc_8427_member_names() {
+        let value = round_trip("example.com. 300 IN MX 10 mail.example.com.");
+        assert_eq!(
+            value,
+            json!({
+                "NAME": "example.com.",
+                "TYPE": 15,
+                "TYPEname": "MX",
+                "CLASS": 1,
+                "CLASSname": "IN",
+                "TTL": 300,
+                "rdataMX": "10 mail.example.com.",
+            })
+        );
+    }
+
+    #[test]
+    fn round_trips_txt() {
+        let value = round_trip(r#"example.com. 300 IN TXT "say \"hi\"" "\000\255""#);
+        assert_eq!(value["rdataTXT"], r#""say \"hi\"" "\000\255""#);
+
+        let value = round_trip("example.com. 300 IN TXT \"\"");
+        assert_eq!(value["rdataTXT"], "\"\"");
+    }
+
+    #[test]
+    fn round_trips_escaped_names() {
+        let value = round_trip(r"a\.b.example.com. 300 IN CNAME \032space\\.example.com.");
+        assert_eq!(value["NAME"], r"a\.b.example.com.");
+        assert_eq!(value["rdataCNAME"], r"\032space\\.example.com.");
+    }
+
+    #[test]
+    fn round_trips_unknown_types_as_hex() {
+        let value = round_trip("example.com. 300 CLASS42 TYPE999 \\# 3 0102ff");
+        assert_eq!(value["TYPE"], 999);
+        assert_eq!(value["CLASS"], 42);
+        assert_eq!(value["RDATAHEX"], "0102FF");
+
+        let value = round_trip("example.com. 300 IN TYPE999 \\# 0");
+        assert_eq!(value["RDATAHEX"], "");
+    }
+
+    #[test]
+    fn round_trips_opt() {
+        let value = round_trip(". 32768 CLASS4096 OPT \\# 6 000a00020102");
+        assert_eq!(value["CLASS"], 4096);
+        assert_eq!(value["TTL"], 32768);
+        assert_eq!(value["RDATAHEX"], "000A00020102");
+        assert!(value.get("CLASSname").is_none());
+    }
+
+    #[test]
+    fn accepts_names_instead_of_numbers() {
+        let rec: DnsRecord = serde_json::from_value(json!({
+            "NAME": "example.com",
+            "TYPEname": "A",
+            "rdataA": "192.0.2.1",
+        }))
+        .unwrap();
+        assert_eq!(rec.to_string(), "example.com. 0 IN A 192.0.2.1");
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        for value in &[
+            json!({ "TYPE": 1, "rdataA": "192.0.2.1" }),
+            json!({ "NAME": "example.com.", "rdataA": "192.0.2.1" }),
+            json!({ "NAME": "example.com.", "TYPE": 1 }),
+            json!({ "NAME": "example.com.", "TYPE": "1", "rdataA": "192.0.2.1" }),
+            json!({ "NAME": "example.com.", "TYPE": 1, "rdataA": "192.0.2" }),
+            json!({ "NAME": "example.com.", "TYPE": 999, "RDATAHEX": "123" }),
+            json!({ "NAME": "example.com.", "TYPE": 1, "RDATAHEX": "c00002" }),
+            json!({ "NAME": "example.com.", "TYPE": 65537, "rdataA": "192.0.2.1" }),
+            json!({ "NAME": "example.com.", "TYPE": 1, "CLASS": 65537, "rdataA": "192.0.2.1" }),
+        ] {
+            assert!(
+                serde_json::from_value::<DnsRecord>(value.clone()).is_err(),
+                "{} parsed",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn round_trips_packets() {
+        let mut packet = DnsPacket::new();
+        packet.header.id = 1234;
+        packet.header.response = true;
+        packet.header.recursion_desired = true;
+        packet.header.rescode = ResultCode::NXDOMAIN;
+        packet
+            .questions
+            .push("www.example.com. IN A".parse().unwrap());
+        packet.authorities.push(
+            "example.com. 3600 IN SOA ns1.example.com. admin.example.com. 1 7200 900 1209600 300"
+                .parse()
+                .unwrap(),
+        );
+        packet.set_edns(1232, true);
+
+        let value = serde_json::to_value(&packet).unwrap();
+        assert_eq!(value["ID"], 1234);
+        assert_eq!(value["QR"], true);
+        assert_eq!(value["RCODE"], 3);
+        assert_eq!(value["questionRRs"][0]["QNAME"], "www.example.com.");
+        assert_eq!(value["questionRRs"][0]["QTYPEname"], "A");
+
+        let parsed: DnsPacket = serde_json::from_value(value.clone()).unwrap();
+        assert_eq!(parsed.questions, packet.questions);
+        assert_eq!(parsed.answers, packet.answers);
+        assert_eq!(parsed.authorities, packet.authorities);
+        assert_eq!(parsed.resources, packet.resources);
+        assert_eq!(parsed.edns_payload_size(), Some(1232));
+        assert!(parsed.edns_dnssec_ok());
+
+        // The counts follow the sections, even though the packet was never
+        // written and its header still says 0.
+        assert_eq!(packet.header.questions, 0);
+        assert_eq!(value["QDCOUNT"], 1);
+        assert_eq!(value["ANCOUNT"], 0);
+        assert_eq!(value["NSCOUNT"], 1);
+        assert_eq!(value["ARCOUNT"], 1);
+        assert_eq!(parsed.header.questions, 1);
+        assert_eq!(parsed.header.answers, 0);
+        assert_eq!(parsed.header.authoritative_entries, 1);
+        assert_eq!(parsed.header.resource_entries, 1);
+    }
+}
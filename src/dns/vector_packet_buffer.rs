@@ -0,0 +1,198 @@
+use super::dns_error::{DnsError, DnsErrorKind, Result};
+use super::packet_buffer::{NameCompression, PacketBuffer};
+
+/// The largest message that can be carried over TCP, where the length prefix
+/// is a 16 bit integer.
+pub const MAX_PACKET_SIZE: usize = 65535;
+
+/// A buffer backed by a `Vec` which grows as data is written, up to a
+/// configurable maximum size. Used for TCP, EDNS0 sized UDP payloads and
+/// anything else that doesn't fit in 512 bytes.
+pub struct VectorPacketBuffer {
+    pub buf: Vec<u8>,
+    pub pos: usize,
+    max_size: usize,
+    names: NameCompression,
+}
+
+impl Default for VectorPacketBuffer {
+    fn default() -> Self {
+        VectorPacketBuffer::new()
+    }
+}
+
+impl VectorPacketBuffer {
+    /// Creates an empty buffer that can grow up to `MAX_PACKET_SIZE` bytes.
+    pub fn new() -> VectorPacketBuffer {
+        VectorPacketBuffer::with_max_size(MAX_PACKET_SIZE)
+    }
+
+    /// Creates an empty buffer that refuses to grow past `max_size` bytes.
+    /// The limit is capped at `MAX_PACKET_SIZE`.
+    pub fn with_max_size(max_size: usize) -> VectorPacketBuffer {
+        VectorPacketBuffer {
+            buf: Vec::new(),
+            pos: 0,
+            max_size: max_size.min(MAX_PACKET_SIZE),
+            names: NameCompression::new(),
+        }
+    }
+
+    /// Creates a buffer for reading a packet that has already been received.
+    pub fn from_bytes(data: &[u8]) -> VectorPacketBuffer {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer
+            .buf
+            .extend_from_slice(&data[..data.len().min(MAX_PACKET_SIZE)]);
+        buffer
+    }
+
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Enables or disables name compression, as `NameCompression::set_enabled`.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.names.set_enabled(enabled);
+    }
+}
+
+impl PacketBuffer for VectorPacketBuffer {
+    fn pos(&self) -> usize {
+        self.pos
+    }
+
+    fn step(&mut self, steps: usize) -> Result<()> {
+        self.seek(self.pos.saturating_add(steps))
+    }
+
+    fn seek(&mut self, pos: usize) -> Result<()> {
+        if pos > self.buf.len() {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<u8> {
+        if self.pos >= self.buf.len() {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, self.pos));
+        }
+
+        let res = self.buf[self.pos];
+        self.pos += 1;
+
+        Ok(res)
+    }
+
+    fn write(&mut self, val: u8) -> Result<()> {
+        if self.pos >= self.max_size {
+            return Err(DnsError::new(DnsErrorKind::BufferFull, self.pos));
+        }
+
+        // Grow the buffer if we're writing past its current end.
+        if self.pos >= self.buf.len() {
+            self.buf.resize(self.pos + 1, 0);
+        }
+        self.buf[self.pos] = val;
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn set(&mut self, pos: usize, val: u8) -> Result<()> {
+        if pos >= self.buf.len() {
+            return Err(DnsError::new(DnsErrorKind::BufferFull, pos));
+        }
+        self.buf[pos] = val;
+
+        Ok(())
+    }
+
+    fn get(&mut self, pos: usize) -> Result<u8> {
+        if pos >= self.buf.len() {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, pos));
+        }
+        Ok(self.buf[pos])
+    }
+
+    fn get_range(&mut self, start: usize, len: usize) -> Result<&[u8]> {
+        if start.saturating_add(len) > self.buf.len() {
+            return Err(DnsError::new(DnsErrorKind::EndOfBuffer, start));
+        }
+        Ok(&self.buf[start..start + len])
+    }
+
+    fn find_label(&self, label: &str) -> Option<usize> {
+        self.names.find(label)
+    }
+
+    fn save_label(&mut self, label: &str, pos: usize) {
+        self.names.save(label, pos);
+    }
+
+    fn compression(&self) -> bool {
+        self.names.is_enabled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kind<T>(res: Result<T>) -> DnsErrorKind {
+        res.err().unwrap().kind
+    }
+
+    #[test]
+    fn seek_and_step_stop_at_the_end_of_the_data() {
+        let mut buffer = VectorPacketBuffer::from_bytes(&[1, 2, 3, 4]);
+        buffer.seek(4).unwrap();
+        assert_eq!(kind(buffer.seek(5)), DnsErrorKind::EndOfBuffer);
+
+        buffer.seek(2).unwrap();
+        assert_eq!(kind(buffer.step(3)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(kind(buffer.step(usize::MAX)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(buffer.pos(), 2);
+        buffer.step(2).unwrap();
+        assert_eq!(buffer.pos(), 4);
+    }
+
+    #[test]
+    fn reads_stop_at_the_end_of_the_data() {
+        let mut buffer = VectorPacketBuffer::from_bytes(&[1, 2, 3]);
+        assert_eq!(kind(buffer.read_u32()), DnsErrorKind::EndOfBuffer);
+
+        assert_eq!(buffer.get(2).unwrap(), 3);
+        assert_eq!(kind(buffer.get(3)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(buffer.get_range(1, 2).unwrap(), &[2, 3]);
+        assert_eq!(kind(buffer.get_range(1, 3)), DnsErrorKind::EndOfBuffer);
+        assert_eq!(
+            kind(buffer.get_range(1, usize::MAX)),
+            DnsErrorKind::EndOfBuffer
+        );
+    }
+
+    #[test]
+    fn writes_grow_up_to_max_size() {
+        let mut buffer = VectorPacketBuffer::with_max_size(3);
+        buffer.write_u16(0x0102).unwrap();
+        buffer.write(3).unwrap();
+        assert_eq!(buffer.buf, vec![1, 2, 3]);
+        assert_eq!(kind(buffer.write(4)), DnsErrorKind::BufferFull);
+    }
+
+    #[test]
+    fn set_only_overwrites_written_data() {
+        let mut buffer = VectorPacketBuffer::new();
+        buffer.write(1).unwrap();
+        buffer.set(0, 2).unwrap();
+        assert_eq!(buffer.buf, vec![2]);
+        assert_eq!(kind(buffer.set(1, 3)), DnsErrorKind::BufferFull);
+    }
+
+    #[test]
+    fn from_bytes_truncates_to_max_packet_size() {
+        let buffer = VectorPacketBuffer::from_bytes(&vec![0; MAX_PACKET_SIZE + 1]);
+        assert_eq!(buffer.buf.len(), MAX_PACKET_SIZE);
+    }
+}
@@ -1,2 +1,4 @@
+#![allow(clippy::upper_case_acronyms)]
+
 pub mod dns;
 pub mod web;
@@ -1,14 +1,14 @@
 use std::fs::File;
 use std::io::Read;
 
-mod dns;
-use dns::byte_packet_buffer::BytePacketBuffer;
-use dns::dns_packet::DnsPacket;
+extern crate dns_server;
+use dns_server::dns::byte_packet_buffer::BytePacketBuffer;
+use dns_server::dns::dns_packet::DnsPacket;
 
 fn main() {
     let mut f = File::open("response_packet.txt").unwrap();
     let mut buffer = BytePacketBuffer::new();
-    f.read(&mut buffer.buf).unwrap();
+    let _ = f.read(&mut buffer.buf).unwrap();
 
     let packet = DnsPacket::from_buffer(&mut buffer).unwrap();
     println!("{:#?}", packet.header);
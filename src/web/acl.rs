@@ -0,0 +1,171 @@
+//! Access control lists restricting which clients the server answers.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// A range of addresses given as a prefix, like `192.168.0.0/16`. A bare
+/// address is a prefix of its full length.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Subnet {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Subnet {
+    /// Whether `addr` lies in the subnet. IPv4 addresses mapped into IPv6,
+    /// as seen on dual stack sockets, match the IPv4 subnets.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                prefix_matches(&net.octets(), &addr.octets(), self.prefix_len)
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                prefix_matches(&net.octets(), &addr.octets(), self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+}
+
+fn prefix_matches(net: &[u8], addr: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = (prefix_len / 8) as usize;
+    if net[..full_bytes] != addr[..full_bytes] {
+        return false;
+    }
+
+    let rest = prefix_len % 8;
+    if rest == 0 {
+        return true;
+    }
+    let mask = 0xFFu8 << (8 - rest);
+    net[full_bytes] & mask == addr[full_bytes] & mask
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Subnet, String> {
+        let (addr, prefix_len) = match s.split_once('/') {
+            Some((addr, len)) => (addr, Some(len)),
+            None => (s, None),
+        };
+
+        let addr = addr
+            .parse::<IpAddr>()
+            .map_err(|_| "invalid address".to_string())?;
+        let max_len = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => match len.parse::<u8>() {
+                Ok(len) if len <= max_len => len,
+                _ => return Err("invalid prefix length".to_string()),
+            },
+            None => max_len,
+        };
+
+        Ok(Subnet { addr, prefix_len })
+    }
+}
+
+/// The clients the server answers. Requests from anywhere else are REFUSED.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Acl {
+    pub allow: Vec<Subnet>,
+}
+
+impl Acl {
+    /// An ACL that lets every client in.
+    pub fn allow_all() -> Acl {
+        Acl {
+            allow: vec!["0.0.0.0/0".parse().unwrap(), "::/0".parse().unwrap()],
+        }
+    }
+
+    pub fn allows(&self, addr: IpAddr) -> bool {
+        self.allow.iter().any(|subnet| subnet.contains(addr))
+    }
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Acl::allow_all()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subnet(s: &str) -> Subnet {
+        s.parse().unwrap()
+    }
+
+    fn contains(net: &str, addr: &str) -> bool {
+        subnet(net).contains(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn matches_prefixes_bit_by_bit() {
+        assert!(contains("0.0.0.0/0", "203.0.113.7"));
+        assert!(contains("::/0", "2001:db8::1"));
+
+        // A /12 covers 172.16.0.0 to 172.31.255.255.
+        assert!(contains("172.16.0.0/12", "172.16.0.1"));
+        assert!(contains("172.16.0.0/12", "172.31.255.255"));
+        assert!(!contains("172.16.0.0/12", "172.32.0.0"));
+        assert!(!contains("172.16.0.0/12", "172.15.255.255"));
+
+        assert!(contains("192.0.2.1/32", "192.0.2.1"));
+        assert!(!contains("192.0.2.1/32", "192.0.2.0"));
+        assert!(contains("192.0.2.1", "192.0.2.1"));
+
+        assert!(contains("2001:db8::/127", "2001:db8::1"));
+        assert!(!contains("2001:db8::/127", "2001:db8::2"));
+        assert!(contains("2001:db8::1/128", "2001:db8::1"));
+        assert!(!contains("2001:db8::1/128", "2001:db8::"));
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_addresses() {
+        assert!(contains("127.0.0.0/8", "::ffff:127.0.0.1"));
+        assert!(!contains("127.0.0.0/8", "::ffff:10.0.0.1"));
+        assert!(contains("0.0.0.0/0", "::ffff:10.0.0.1"));
+        assert!(!contains("::/0", "127.0.0.1"));
+        assert!(!contains("0.0.0.0/0", "2001:db8::1"));
+    }
+
+    #[test]
+    fn parses_and_formats_subnets() {
+        assert_eq!(subnet("10.0.0.0/8").to_string(), "10.0.0.0/8");
+        assert_eq!(subnet("10.1.2.3").prefix_len, 32);
+        assert_eq!(subnet("::1").prefix_len, 128);
+
+        for s in &[
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "10.0.0.0/x",
+        ] {
+            assert!(s.parse::<Subnet>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn allows_any_listed_subnet() {
+        let acl = Acl {
+            allow: vec![subnet("10.0.0.0/8"), subnet("2001:db8::/32")],
+        };
+        assert!(acl.allows("10.9.8.7".parse().unwrap()));
+        assert!(acl.allows("2001:db8:1::1".parse().unwrap()));
+        assert!(!acl.allows("192.0.2.1".parse().unwrap()));
+        assert!(!Acl { allow: Vec::new() }.allows("10.0.0.1".parse().unwrap()));
+        assert!(Acl::default().allows("::ffff:192.0.2.1".parse().unwrap()));
+    }
+}
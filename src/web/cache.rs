@@ -0,0 +1,324 @@
+//! A cache of recursive lookup results, shared by all the threads answering
+//! queries.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::dns::dns_packet::DnsPacket;
+use crate::dns::dns_question::DnsQuestion;
+use crate::dns::dns_record::DnsRecord;
+use crate::dns::name::Name;
+use crate::dns::query_class::QueryClass;
+use crate::dns::query_type::QueryType;
+use crate::dns::result_code::ResultCode;
+
+type CacheKey = (Name, QueryType, QueryClass);
+
+/// Orders entries by when they expire. The sequence number tells apart
+/// entries that expire at the same instant.
+type ExpiryKey = (Instant, u64);
+
+struct CacheEntry {
+    packet: DnsPacket,
+    stored: Instant,
+    expiry: ExpiryKey,
+}
+
+/// The cached entries, along with an index of them by expiry so that making
+/// room doesn't need a scan of the whole cache.
+#[derive(Default)]
+struct Entries {
+    map: HashMap<CacheKey, CacheEntry>,
+    by_expiry: BTreeMap<ExpiryKey, CacheKey>,
+    next_seq: u64,
+}
+
+impl Entries {
+    /// Adds an entry for a key that isn't in the cache.
+    fn insert(&mut self, key: CacheKey, packet: DnsPacket, stored: Instant, expires: Instant) {
+        let expiry = (expires, self.next_seq);
+        self.next_seq += 1;
+        self.by_expiry.insert(expiry, key.clone());
+        self.map.insert(
+            key,
+            CacheEntry {
+                packet,
+                stored,
+                expiry,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.map.remove(key) {
+            self.by_expiry.remove(&entry.expiry);
+        }
+    }
+
+    /// Drops the entry that expires first, if there is one.
+    fn remove_first(&mut self) {
+        if let Some((_, key)) = self.by_expiry.pop_first() {
+            self.map.remove(&key);
+        }
+    }
+}
+
+/// Maps questions to the responses they were last answered with, until the
+/// shortest TTL in the response runs out.
+pub struct Cache {
+    entries: Mutex<Entries>,
+    max_entries: usize,
+}
+
+impl Cache {
+    /// Creates a cache holding at most `max_entries` responses. A size of 0
+    /// disables caching.
+    pub fn new(max_entries: usize) -> Cache {
+        Cache {
+            entries: Mutex::new(Entries::default()),
+            max_entries,
+        }
+    }
+
+    fn key(question: &DnsQuestion) -> CacheKey {
+        (question.name.clone(), question.qtype, question.qclass)
+    }
+
+    /// Returns the cached response to `question`, with the TTLs reduced by the
+    /// time it has been cached.
+    pub fn lookup(&self, question: &DnsQuestion) -> Option<DnsPacket> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+
+        let key = Cache::key(question);
+        let expired = match entries.map.get(&key) {
+            Some(entry) => entry.expiry.0 <= now,
+            None => return None,
+        };
+        if expired {
+            entries.remove(&key);
+            return None;
+        }
+
+        let entry = &entries.map[&key];
+        let age = now.duration_since(entry.stored).as_secs() as u32;
+        let mut packet = entry.packet.clone();
+        for rec in packet
+            .answers
+            .iter_mut()
+            .chain(packet.authorities.iter_mut())
+            .chain(packet.resources.iter_mut())
+        {
+            let ttl = rec.ttl();
+            rec.set_ttl(ttl.saturating_sub(age));
+        }
+
+        Some(packet)
+    }
+
+    /// Stores a response that answers the question: either one with
+    /// answers, or a negative response (NXDOMAIN or NODATA) that carries the
+    /// zone's SOA record, as RFC 2308 requires for caching it. Referrals and
+    /// anything else are left out, so that e.g. a referral we gave up on
+    /// isn't served as NODATA for as long as the NS records live.
+    pub fn store(&self, question: &DnsQuestion, packet: &DnsPacket) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        let ttl = match packet.rescode() {
+            ResultCode::NOERROR if !packet.answers.is_empty() => {
+                packet.answers.iter().map(|rec| rec.ttl()).min()
+            }
+            ResultCode::NOERROR | ResultCode::NXDOMAIN => packet
+                .authorities
+                .iter()
+                .filter_map(|rec| match *rec {
+                    // Negative answers are cached for the SOA minimum.
+                    DnsRecord::SOA { minimum, ttl, .. } => Some(minimum.min(ttl)),
+                    _ => None,
+                })
+                .min(),
+            _ => None,
+        };
+        let ttl = match ttl {
+            Some(ttl) if ttl > 0 => ttl,
+            _ => return,
+        };
+
+        let now = Instant::now();
+        let key = Cache::key(question);
+        let mut entries = self.entries.lock().unwrap();
+
+        // Make room by dropping whatever expires first, which includes
+        // anything that has expired already. A response that replaces an
+        // older one for the same question needs no room.
+        entries.remove(&key);
+        while entries.map.len() >= self.max_entries {
+            entries.remove_first();
+        }
+
+        entries.insert(
+            key,
+            packet.clone(),
+            now,
+            now + Duration::from_secs(ttl as u64),
+        );
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question() -> DnsQuestion {
+        DnsQuestion::new("www.example.com".parse().unwrap(), QueryType::A)
+    }
+
+    fn response(rescode: ResultCode, answers: &[&str], authorities: &[&str]) -> DnsPacket {
+        let mut packet = DnsPacket::new().with_rescode(rescode);
+        packet.questions.push(question());
+        for rec in answers {
+            packet.answers.push(rec.parse().unwrap());
+        }
+        for rec in authorities {
+            packet.authorities.push(rec.parse().unwrap());
+        }
+        packet
+    }
+
+    const SOA: &str =
+        "example.com. 3600 IN SOA ns1.example.com. admin.example.com. 1 7200 900 86400 300";
+
+    #[test]
+    fn caches_answers() {
+        let cache = Cache::new(10);
+        let packet = response(
+            ResultCode::NOERROR,
+            &["www.example.com. 300 IN A 192.0.2.1"],
+            &[],
+        );
+        cache.store(&question(), &packet);
+
+        let cached = cache.lookup(&question()).unwrap();
+        assert_eq!(cached.answers.len(), 1);
+        assert!(cached.answers[0].ttl() <= 300);
+    }
+
+    #[test]
+    fn caches_negative_responses_with_soa() {
+        let cache = Cache::new(10);
+        cache.store(&question(), &response(ResultCode::NXDOMAIN, &[], &[SOA]));
+        assert_eq!(
+            cache.lookup(&question()).unwrap().rescode(),
+            ResultCode::NXDOMAIN
+        );
+
+        let cache = Cache::new(10);
+        cache.store(&question(), &response(ResultCode::NOERROR, &[], &[SOA]));
+        assert!(cache.lookup(&question()).unwrap().answers.is_empty());
+    }
+
+    #[test]
+    fn skips_negative_responses_without_soa() {
+        let cache = Cache::new(10);
+        cache.store(&question(), &response(ResultCode::NXDOMAIN, &[], &[]));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn skips_referrals() {
+        let cache = Cache::new(10);
+        let packet = response(
+            ResultCode::NOERROR,
+            &[],
+            &["example.com. 172800 IN NS ns1.example.com."],
+        );
+        cache.store(&question(), &packet);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn skips_failures() {
+        let cache = Cache::new(10);
+        cache.store(&question(), &response(ResultCode::SERVFAIL, &[], &[SOA]));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn evicts_soonest_expiring_when_full() {
+        let cache = Cache::new(1);
+        let other = DnsQuestion::new("mail.example.com".parse().unwrap(), QueryType::A);
+        cache.store(
+            &question(),
+            &response(
+                ResultCode::NOERROR,
+                &["www.example.com. 30 IN A 192.0.2.1"],
+                &[],
+            ),
+        );
+        cache.store(
+            &other,
+            &response(
+                ResultCode::NOERROR,
+                &["mail.example.com. 300 IN A 192.0.2.2"],
+                &[],
+            ),
+        );
+
+        assert_eq!(cache.len(), 1);
+        assert!(cache.lookup(&question()).is_none());
+        assert!(cache.lookup(&other).is_some());
+    }
+
+    #[test]
+    fn evicts_in_expiry_order() {
+        let cache = Cache::new(3);
+        let question = |i: u32| {
+            DnsQuestion::new(
+                format!("host{}.example.com", i).parse().unwrap(),
+                QueryType::A,
+            )
+        };
+        let answer = |i: u32, ttl: u32| {
+            response(
+                ResultCode::NOERROR,
+                &[&format!(
+                    "host{}.example.com. {} IN A 192.0.2.{}",
+                    i, ttl, i
+                )],
+                &[],
+            )
+        };
+
+        cache.store(&question(1), &answer(1, 300));
+        cache.store(&question(2), &answer(2, 100));
+        cache.store(&question(3), &answer(3, 200));
+
+        // Replacing an entry moves it in the expiry order without evicting
+        // anything.
+        cache.store(&question(2), &answer(2, 400));
+        assert_eq!(cache.len(), 3);
+
+        cache.store(&question(4), &answer(4, 500));
+        assert!(cache.lookup(&question(3)).is_none());
+        cache.store(&question(5), &answer(5, 500));
+        assert!(cache.lookup(&question(1)).is_none());
+
+        assert_eq!(cache.len(), 3);
+        for i in &[2, 4, 5] {
+            assert!(cache.lookup(&question(*i)).is_some(), "host{}", i);
+        }
+        let entries = cache.entries.lock().unwrap();
+        assert_eq!(entries.by_expiry.len(), 3);
+    }
+}
@@ -0,0 +1,568 @@
+//! Loading `ServerOptions` from a TOML file, with overrides from the command
+//! line. A file looks like this, where every setting is optional and the
+//! values shown are the defaults:
+//!
+//! ```toml
+//! [server]
+//! listen = ["0.0.0.0:2053"]
+//! worker_threads = 8
+//! max_tcp_connections = 128
+//! max_recursive_resolutions = 16
+//!
+//! [resolver]
+//! mode = "recursive"            # or "forwarding"
+//! root_servers = ["198.41.0.4"] # used in recursive mode
+//! forwarders = []               # used in forwarding mode, e.g. "8.8.8.8:53"
+//! randomize_case = false
+//! timeout_ms = 2000             # per query to a server
+//! retries = 1                   # per server, after a timeout
+//! time_budget_ms = 10000        # per client query, across all servers
+//!
+//! [cache]
+//! size = 10000
+//!
+//! [log]
+//! level = "info"                # "error", "info" or "debug"
+//!
+//! [acl]
+//! allow = ["0.0.0.0/0", "::/0"]
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use toml::value::{Table, Value};
+
+use super::acl::{Acl, Subnet};
+use super::log::LogLevel;
+use super::server::{ResolverMode, ServerOptions};
+
+/// The type of value a setting holds.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Str,
+    Int,
+    Bool,
+    List,
+}
+
+/// Every setting, as `section.key`.
+const SETTINGS: &[(&str, Kind)] = &[
+    ("server.listen", Kind::List),
+    ("server.worker_threads", Kind::Int),
+    ("server.max_tcp_connections", Kind::Int),
+    ("server.max_recursive_resolutions", Kind::Int),
+    ("resolver.mode", Kind::Str),
+    ("resolver.root_servers", Kind::List),
+    ("resolver.forwarders", Kind::List),
+    ("resolver.randomize_case", Kind::Bool),
+    ("resolver.timeout_ms", Kind::Int),
+    ("resolver.retries", Kind::Int),
+    ("resolver.time_budget_ms", Kind::Int),
+    ("cache.size", Kind::Int),
+    ("log.level", Kind::Str),
+    ("acl.allow", Kind::List),
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file couldn't be read.
+    Io { path: String, error: io::Error },
+    /// The file isn't valid TOML.
+    Parse {
+        path: String,
+        error: toml::de::Error,
+    },
+    /// A setting that doesn't exist.
+    UnknownSetting { key: String },
+    /// A setting with a value of the wrong type or out of range.
+    Invalid { key: String, reason: String },
+}
+
+impl ConfigError {
+    fn invalid<S: Into<String>>(key: &str, reason: S) -> ConfigError {
+        ConfigError::Invalid {
+            key: key.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io {
+                ref path,
+                ref error,
+            } => {
+                write!(f, "Failed to read config file {}: {}", path, error)
+            }
+            ConfigError::Parse {
+                ref path,
+                ref error,
+            } => {
+                write!(f, "Failed to parse config file {}: {}", path, error)
+            }
+            ConfigError::UnknownSetting { ref key } => write!(f, "Unknown setting {}", key),
+            ConfigError::Invalid {
+                ref key,
+                ref reason,
+            } => {
+                write!(f, "Invalid value for {}: {}", key, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The settings from a config file and the command line, before they are
+/// checked and turned into `ServerOptions`.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    settings: Table,
+}
+
+impl Config {
+    /// A config with every setting left at its default.
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    pub fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|error| ConfigError::Io {
+            path: path.to_string(),
+            error,
+        })?;
+
+        Config::parse(&text, path)
+    }
+
+    /// Parses the text of a config file. `path` is only used in errors.
+    pub fn parse(text: &str, path: &str) -> Result<Config, ConfigError> {
+        let settings = toml::from_str::<Table>(text).map_err(|error| ConfigError::Parse {
+            path: path.to_string(),
+            error,
+        })?;
+
+        Ok(Config { settings })
+    }
+
+    /// Overrides a setting, given as `section.key`, with a value from the
+    /// command line. Lists are given as comma separated values.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let (section, name) = match key.split_once('.') {
+            Some(x) => x,
+            None => {
+                return Err(ConfigError::UnknownSetting {
+                    key: key.to_string(),
+                })
+            }
+        };
+
+        let value = match setting_kind(key)? {
+            Kind::Str => Value::String(value.to_string()),
+            Kind::Int => match value.parse::<i64>() {
+                Ok(x) => Value::Integer(x),
+                Err(_) => return Err(ConfigError::invalid(key, "expected an integer")),
+            },
+            Kind::Bool => match value {
+                "true" => Value::Boolean(true),
+                "false" => Value::Boolean(false),
+                _ => return Err(ConfigError::invalid(key, "expected true or false")),
+            },
+            Kind::List => Value::Array(
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            ),
+        };
+
+        let entry = self
+            .settings
+            .entry(section.to_string())
+            .or_insert_with(|| Value::Table(Table::new()));
+        match *entry {
+            Value::Table(ref mut table) => {
+                table.insert(name.to_string(), value);
+            }
+            _ => return Err(ConfigError::invalid(section, "expected a section")),
+        }
+
+        Ok(())
+    }
+
+    /// Checks every setting, and builds the options to run the server with.
+    /// Settings that aren't given keep the defaults of `ServerOptions::new`.
+    pub fn to_options(&self) -> Result<ServerOptions, ConfigError> {
+        self.check_known()?;
+
+        let mut options = ServerOptions::new();
+
+        if let Some(addrs) = self.list("server.listen")? {
+            options.listen = addrs
+                .iter()
+                .map(|addr| parse_item::<SocketAddr>("server.listen", addr))
+                .collect::<Result<_, _>>()?;
+            if options.listen.is_empty() {
+                return Err(ConfigError::invalid(
+                    "server.listen",
+                    "at least one address is needed",
+                ));
+            }
+        }
+        if let Some(x) = self.positive_int("server.worker_threads")? {
+            options.worker_threads = x;
+        }
+        if let Some(x) = self.positive_int("server.max_tcp_connections")? {
+            options.max_tcp_connections = x;
+        }
+        if let Some(x) = self.positive_int("server.max_recursive_resolutions")? {
+            options.max_recursive_resolutions = x;
+        }
+
+        options.mode = self.mode()?;
+        if let Some(x) = self.bool("resolver.randomize_case")? {
+            options.lookup.randomize_case = x;
+        }
+        if let Some(x) = self.positive_int("resolver.timeout_ms")? {
+            options.lookup.timeout = Duration::from_millis(x as u64);
+        }
+        if let Some(x) = self.int("resolver.retries")? {
+            options.lookup.retries = x;
+        }
+        if let Some(x) = self.positive_int("resolver.time_budget_ms")? {
+            options.lookup.time_budget = Duration::from_millis(x as u64);
+        }
+
+        if let Some(x) = self.int("cache.size")? {
+            options.cache_size = x;
+        }
+
+        if let Some(level) = self.str("log.level")? {
+            options.log_level = level
+                .parse::<LogLevel>()
+                .map_err(|e| ConfigError::invalid("log.level", e))?;
+        }
+
+        if let Some(subnets) = self.list("acl.allow")? {
+            options.acl = Acl {
+                allow: subnets
+                    .iter()
+                    .map(|subnet| parse_item::<Subnet>("acl.allow", subnet))
+                    .collect::<Result<_, _>>()?,
+            };
+        }
+
+        Ok(options)
+    }
+
+    fn mode(&self) -> Result<ResolverMode, ConfigError> {
+        let root_servers = match self.list("resolver.root_servers")? {
+            Some(addrs) => Some(
+                addrs
+                    .iter()
+                    .map(|addr| parse_item::<IpAddr>("resolver.root_servers", addr))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => None,
+        };
+        // Forwarders are usually on port 53, so it may be left out.
+        let forwarders = match self.list("resolver.forwarders")? {
+            Some(addrs) => addrs
+                .iter()
+                .map(|addr| match addr.parse::<IpAddr>() {
+                    Ok(ip) => Ok(SocketAddr::new(ip, 53)),
+                    Err(_) => parse_item::<SocketAddr>("resolver.forwarders", addr),
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+
+        match self.str("resolver.mode")?.unwrap_or("recursive") {
+            "recursive" => match root_servers {
+                Some(root_servers) if root_servers.is_empty() => Err(ConfigError::invalid(
+                    "resolver.root_servers",
+                    "at least one root server is needed in recursive mode",
+                )),
+                Some(root_servers) => Ok(ResolverMode::Recursive { root_servers }),
+                None => Ok(ResolverMode::default()),
+            },
+            "forwarding" => {
+                if forwarders.is_empty() {
+                    return Err(ConfigError::invalid(
+                        "resolver.forwarders",
+                        "at least one forwarder is needed in forwarding mode",
+                    ));
+                }
+                Ok(ResolverMode::Forwarding { forwarders })
+            }
+            mode => Err(ConfigError::invalid(
+                "resolver.mode",
+                format!("unknown mode {:?}, expected recursive or forwarding", mode),
+            )),
+        }
+    }
+
+    /// Rejects settings that don't exist, which are most likely typos.
+    fn check_known(&self) -> Result<(), ConfigError> {
+        for (section, value) in &self.settings {
+            let table = match *value {
+                Value::Table(ref table) => table,
+                _ => {
+                    return Err(ConfigError::invalid(section, "expected a section"));
+                }
+            };
+            for name in table.keys() {
+                setting_kind(&format!("{}.{}", section, name))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<&Value> {
+        let (section, name) = key.split_once('.')?;
+        self.settings.get(section)?.get(name)
+    }
+
+    fn str(&self, key: &str) -> Result<Option<&str>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::String(x)) => Ok(Some(x)),
+            Some(_) => Err(ConfigError::invalid(key, "expected a string")),
+        }
+    }
+
+    fn bool(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Boolean(x)) => Ok(Some(*x)),
+            Some(_) => Err(ConfigError::invalid(key, "expected true or false")),
+        }
+    }
+
+    fn int(&self, key: &str) -> Result<Option<usize>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(x)) if *x >= 0 => Ok(Some(*x as usize)),
+            Some(Value::Integer(_)) => Err(ConfigError::invalid(key, "must not be negative")),
+            Some(_) => Err(ConfigError::invalid(key, "expected an integer")),
+        }
+    }
+
+    fn positive_int(&self, key: &str) -> Result<Option<usize>, ConfigError> {
+        match self.int(key)? {
+            Some(0) => Err(ConfigError::invalid(key, "must be at least 1")),
+            x => Ok(x),
+        }
+    }
+
+    fn list(&self, key: &str) -> Result<Option<Vec<&str>>, ConfigError> {
+        let items = match self.get(key) {
+            None => return Ok(None),
+            Some(Value::Array(items)) => items,
+            Some(_) => return Err(ConfigError::invalid(key, "expected a list of strings")),
+        };
+
+        items
+            .iter()
+            .map(|item| match *item {
+                Value::String(ref x) => Ok(x.as_str()),
+                _ => Err(ConfigError::invalid(key, "expected a list of strings")),
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+}
+
+fn setting_kind(key: &str) -> Result<Kind, ConfigError> {
+    SETTINGS
+        .iter()
+        .find(|&&(name, _)| name == key)
+        .map(|&(_, kind)| kind)
+        .ok_or_else(|| ConfigError::UnknownSetting {
+            key: key.to_string(),
+        })
+}
+
+fn parse_item<T>(key: &str, item: &str) -> Result<T, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: fmt::Display,
+{
+    item.parse::<T>()
+        .map_err(|e| ConfigError::invalid(key, format!("{:?}: {}", item, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(text: &str) -> Result<ServerOptions, ConfigError> {
+        Config::parse(text, "test.toml")?.to_options()
+    }
+
+    /// The key an `Invalid` error is about, or the one that is unknown.
+    fn error_key(error: ConfigError) -> String {
+        match error {
+            ConfigError::Invalid { key, .. } | ConfigError::UnknownSetting { key } => key,
+            error => panic!("unexpected error {}", error),
+        }
+    }
+
+    #[test]
+    fn defaults_match_server_options() {
+        let options = options("").unwrap();
+        let defaults = ServerOptions::new();
+        assert_eq!(options.listen, defaults.listen);
+        assert_eq!(options.mode, defaults.mode);
+        assert_eq!(options.worker_threads, defaults.worker_threads);
+        assert_eq!(options.cache_size, defaults.cache_size);
+        assert_eq!(options.acl, defaults.acl);
+        assert_eq!(options.log_level, defaults.log_level);
+    }
+
+    #[test]
+    fn reads_every_section() {
+        let options = options(
+            r#"
+            [server]
+            listen = ["127.0.0.1:53", "[::1]:53"]
+            worker_threads = 2
+            max_tcp_connections = 4
+            max_recursive_resolutions = 1
+
+            [resolver]
+            mode = "forwarding"
+            forwarders = ["192.0.2.1", "192.0.2.2:5353"]
+            randomize_case = true
+            timeout_ms = 500
+            retries = 0
+            time_budget_ms = 3000
+
+            [cache]
+            size = 0
+
+            [log]
+            level = "debug"
+
+            [acl]
+            allow = ["10.0.0.0/8"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            options.listen,
+            vec![
+                "127.0.0.1:53".parse::<SocketAddr>().unwrap(),
+                "[::1]:53".parse().unwrap()
+            ]
+        );
+        assert_eq!(options.worker_threads, 2);
+        assert_eq!(options.max_tcp_connections, 4);
+        assert_eq!(options.max_recursive_resolutions, 1);
+        assert_eq!(
+            options.mode,
+            ResolverMode::Forwarding {
+                forwarders: vec![
+                    "192.0.2.1:53".parse().unwrap(),
+                    "192.0.2.2:5353".parse().unwrap()
+                ]
+            }
+        );
+        assert!(options.lookup.randomize_case);
+        assert_eq!(options.lookup.timeout, Duration::from_millis(500));
+        assert_eq!(options.lookup.retries, 0);
+        assert_eq!(options.lookup.time_budget, Duration::from_secs(3));
+        assert_eq!(options.cache_size, 0);
+        assert_eq!(options.log_level, LogLevel::Debug);
+        assert_eq!(options.acl.allow, vec!["10.0.0.0/8".parse().unwrap()]);
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        let cases = [
+            ("[server]\nlisten = []", "server.listen"),
+            ("[server]\nlisten = [\"localhost\"]", "server.listen"),
+            ("[server]\nlisten = \"127.0.0.1:53\"", "server.listen"),
+            ("[server]\nworker_threads = 0", "server.worker_threads"),
+            (
+                "[server]\nmax_tcp_connections = -1",
+                "server.max_tcp_connections",
+            ),
+            ("[server]\nworkers = 4", "server.workers"),
+            ("[resolver]\nmode = \"forwarding\"", "resolver.forwarders"),
+            ("[resolver]\nmode = \"stub\"", "resolver.mode"),
+            ("[resolver]\nroot_servers = []", "resolver.root_servers"),
+            ("[resolver]\nroot_servers = [1]", "resolver.root_servers"),
+            ("[resolver]\nrandomize_case = 1", "resolver.randomize_case"),
+            ("[resolver]\ntimeout_ms = \"1s\"", "resolver.timeout_ms"),
+            ("[log]\nlevel = \"trace\"", "log.level"),
+            ("[acl]\nallow = [\"10.0.0.0/33\"]", "acl.allow"),
+            ("server = 5", "server"),
+            ("[dns]\nport = 53", "dns.port"),
+        ];
+        for (text, key) in cases.iter() {
+            assert_eq!(error_key(options(text).unwrap_err()), *key, "{}", text);
+        }
+
+        assert!(matches!(
+            Config::parse("[server", "test.toml"),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let mut config = Config::parse(
+            "[server]\nworker_threads = 2\n[resolver]\nrandomize_case = true\n",
+            "test.toml",
+        )
+        .unwrap();
+        config.set("server.worker_threads", "6").unwrap();
+        config
+            .set("server.listen", "127.0.0.1:53, [::1]:53,")
+            .unwrap();
+        config.set("resolver.randomize_case", "false").unwrap();
+        config.set("resolver.mode", "forwarding").unwrap();
+        config.set("resolver.forwarders", "192.0.2.1").unwrap();
+
+        let options = config.to_options().unwrap();
+        assert_eq!(options.worker_threads, 6);
+        assert_eq!(options.listen.len(), 2);
+        assert!(!options.lookup.randomize_case);
+        assert_eq!(
+            options.mode,
+            ResolverMode::Forwarding {
+                forwarders: vec!["192.0.2.1:53".parse().unwrap()]
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_overrides() {
+        let mut config = Config::new();
+        let cases = [
+            ("listen", "127.0.0.1:53"),
+            ("server.port", "53"),
+            ("server.worker_threads", "many"),
+            ("resolver.randomize_case", "yes"),
+        ];
+        for &(key, value) in cases.iter() {
+            assert_eq!(error_key(config.set(key, value).unwrap_err()), key);
+        }
+
+        // An override of a section that the file set to something else must
+        // not quietly replace it.
+        let mut config = Config::parse("server = 5", "test.toml").unwrap();
+        let error = config.set("server.listen", "127.0.0.1:53").unwrap_err();
+        assert_eq!(error_key(error), "server");
+    }
+}
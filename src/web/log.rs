@@ -0,0 +1,84 @@
+//! Process-wide log level for the messages the server prints.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// How much the server logs. Each level includes the ones before it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    /// Only failures.
+    Error,
+    /// Failures, each query received and periodic statistics.
+    #[default]
+    Info,
+    /// Everything, including each step of a recursive lookup and the records
+    /// in every answer.
+    Debug,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+impl LogLevel {
+    /// Sets the level for the whole process.
+    pub fn set(self) {
+        LEVEL.store(self as u8, Ordering::Relaxed);
+    }
+
+    /// Whether messages at this level are printed.
+    pub fn enabled(self) -> bool {
+        self as u8 <= LEVEL.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LogLevel::Error => write!(f, "error"),
+            LogLevel::Info => write!(f, "info"),
+            LogLevel::Debug => write!(f, "debug"),
+        }
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogLevel, String> {
+        match s {
+            "error" => Ok(LogLevel::Error),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            _ => Err(format!(
+                "unknown log level {:?}, expected error, info or debug",
+                s
+            )),
+        }
+    }
+}
+
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        if $crate::web::log::LogLevel::Error.enabled() {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        if $crate::web::log::LogLevel::Info.enabled() {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        if $crate::web::log::LogLevel::Debug.enabled() {
+            println!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {log_debug, log_error, log_info};
@@ -1 +1,6 @@
-pub mod server;
\ No newline at end of file
+pub mod acl;
+pub mod cache;
+pub mod config;
+pub mod log;
+pub mod server;
+pub mod stats;
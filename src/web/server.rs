@@ -1,172 +1,1306 @@
-use std::net::UdpSocket;
-use std::io::Result;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::dns::query_type::QueryType;
+use rand::seq::SliceRandom;
+use rand::{thread_rng, Rng};
+
+use crate::dns::byte_packet_buffer::BytePacketBuffer;
+use crate::dns::dns_error::DnsErrorKind;
 use crate::dns::dns_packet::DnsPacket;
+use crate::dns::dns_packet_ref::DnsPacketRef;
 use crate::dns::dns_question::DnsQuestion;
-use crate::dns::byte_packet_buffer::BytePacketBuffer;
+use crate::dns::dns_record::DnsRecord;
+use crate::dns::edns::DEFAULT_EDNS_PAYLOAD_SIZE;
+use crate::dns::extended_error::{ExtendedError, ExtendedErrorCode};
+use crate::dns::name::Name;
+use crate::dns::opcode::Opcode;
+use crate::dns::packet_buffer::PacketBuffer;
+use crate::dns::query_class::QueryClass;
+use crate::dns::query_type::QueryType;
 use crate::dns::result_code::ResultCode;
+use crate::dns::vector_packet_buffer::{VectorPacketBuffer, MAX_PACKET_SIZE};
 
-fn lookup(qname: &str, qtype: QueryType, server: (&str, u16)) -> Result<DnsPacket> {
-    let socket = UdpSocket::bind(("0.0.0.0", 43210))?;
+use super::acl::Acl;
+use super::cache::Cache;
+use super::log::{log_debug, log_error, log_info, LogLevel};
+use super::stats::Stats;
 
-    let mut packet = DnsPacket::new();
+/// How long a TCP client may take to send each query, counting from the end
+/// of the previous one. Connections that stay idle, or trickle a query in,
+/// for longer are closed.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
 
-    packet.header.id = 6666;
-    packet.header.questions = 1;
-    packet.header.recursion_desired = true;
-    packet.questions.push(DnsQuestion::new(qname.to_string(), qtype));
+/// How often the counters in `Stats` are logged.
+const STATS_INTERVAL: Duration = Duration::from_secs(60);
 
-    let mut req_buffer = BytePacketBuffer::new();
-    packet.write(&mut req_buffer).unwrap();
-    socket.send_to(&req_buffer.buf[0..req_buffer.pos], server)?;
+/// How many UDP queries may wait for a worker before new ones are dropped.
+const UDP_QUEUE_LEN: usize = 1024;
 
-    let mut res_buffer = BytePacketBuffer::new();
-    socket.recv_from(&mut res_buffer.buf).unwrap();
+/// Settings for the queries the server sends while resolving.
+#[derive(Clone, Debug)]
+pub struct LookupOptions {
+    /// Randomize the case of the name in outgoing queries, and reject any
+    /// response that doesn't echo it exactly. Off by default since a few
+    /// servers don't preserve case.
+    pub randomize_case: bool,
+    /// How long to wait for a server to answer a single query.
+    pub timeout: Duration,
+    /// How many more times to send a query to a server that timed out,
+    /// before moving on to the next one.
+    pub retries: usize,
+    /// How long resolving a client's query may take in total, across all
+    /// the servers asked and including any wait for other resolutions to
+    /// finish. The client is sent SERVFAIL once it runs out.
+    pub time_budget: Duration,
+}
 
-    DnsPacket::from_buffer(&mut res_buffer)
+impl LookupOptions {
+    pub fn new() -> LookupOptions {
+        LookupOptions {
+            randomize_case: false,
+            timeout: Duration::from_secs(2),
+            retries: 1,
+            time_budget: Duration::from_secs(10),
+        }
+    }
 }
 
-fn recursive_lookup(qname: &str, qtype: QueryType) -> Result<DnsPacket> {
-    // assume we always start with *a.root-servers.net*
-    let mut ns = "198.41.0.4".to_string();
+impl Default for LookupOptions {
+    fn default() -> Self {
+        LookupOptions::new()
+    }
+}
 
-    loop {
-        println!("Attempting lookup of {:?} {} with ns {}", qtype, qname, ns);
+/// Where the server sends the queries it can't answer itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolverMode {
+    /// Resolve names iteratively, starting from one of the root servers.
+    Recursive { root_servers: Vec<IpAddr> },
+    /// Pass queries on to other recursive resolvers, trying them in order.
+    Forwarding { forwarders: Vec<SocketAddr> },
+}
 
-        // send query to active server
-        let ns_copy = ns.clone();
+impl Default for ResolverMode {
+    fn default() -> Self {
+        // a.root-servers.net
+        ResolverMode::Recursive {
+            root_servers: vec![IpAddr::V4(Ipv4Addr::new(198, 41, 0, 4))],
+        }
+    }
+}
 
-        let server = (ns_copy.as_str(), 53);
-        let response = lookup(qname, qtype.clone(), server)?;
+/// Settings for the server: where it listens, how it resolves queries and
+/// how it shares its work between threads.
+#[derive(Clone, Debug)]
+pub struct ServerOptions {
+    /// The addresses to accept queries on, over both UDP and TCP.
+    pub listen: Vec<SocketAddr>,
+    pub mode: ResolverMode,
+    /// The number of threads answering UDP queries. TCP connections get a
+    /// thread each.
+    pub worker_threads: usize,
+    /// How many TCP connections may be open at once. Connections beyond
+    /// that are closed as soon as they are accepted.
+    pub max_tcp_connections: usize,
+    /// How many recursive resolutions may run at the same time. Queries
+    /// beyond that wait for one to finish, so that a burst of cache misses
+    /// doesn't turn into a burst of upstream traffic.
+    pub max_recursive_resolutions: usize,
+    /// The most responses to keep in the cache, or 0 to disable it.
+    pub cache_size: usize,
+    pub acl: Acl,
+    pub log_level: LogLevel,
+    pub lookup: LookupOptions,
+}
 
-        // if there are entries in the answer section, and no errors, we're done!
-        if !response.answers.is_empty() && 
-            response.header.rescode == ResultCode::NOERROR {
+impl ServerOptions {
+    pub fn new() -> ServerOptions {
+        ServerOptions {
+            listen: vec![SocketAddr::from(([0, 0, 0, 0], 2053))],
+            mode: ResolverMode::default(),
+            worker_threads: 8,
+            max_tcp_connections: 128,
+            max_recursive_resolutions: 16,
+            cache_size: 10000,
+            acl: Acl::allow_all(),
+            log_level: LogLevel::default(),
+            lookup: LookupOptions::default(),
+        }
+    }
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        ServerOptions::new()
+    }
+}
 
-            return Ok(response.clone());
+/// A counting semaphore bounding the number of recursive resolutions.
+struct ResolutionLimit {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Held while a resolution runs, giving its slot back when dropped.
+struct ResolutionPermit<'a> {
+    limit: &'a ResolutionLimit,
+}
+
+impl ResolutionLimit {
+    fn new(max: usize) -> ResolutionLimit {
+        ResolutionLimit {
+            available: Mutex::new(max.max(1)),
+            released: Condvar::new(),
         }
+    }
 
-        if response.header.rescode == ResultCode::NXDOMAIN {
-            return Ok(response.clone());
+    /// Blocks until a resolution may start, or returns `None` if none can
+    /// start before `deadline`.
+    fn acquire(&self, deadline: Instant) -> Option<ResolutionPermit<'_>> {
+        let mut available = self.available.lock().unwrap();
+        while *available == 0 {
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            available = self
+                .released
+                .wait_timeout(available, deadline - now)
+                .unwrap()
+                .0;
         }
+        *available -= 1;
 
-        if let Some(new_ns) = response.get_resolved_ns(qname) {
-            ns = new_ns.clone();
-            continue;
+        Some(ResolutionPermit { limit: self })
+    }
+}
+
+impl Drop for ResolutionPermit<'_> {
+    fn drop(&mut self) {
+        *self.limit.available.lock().unwrap() += 1;
+        self.limit.released.notify_one();
+    }
+}
+
+/// Everything the threads answering queries share.
+struct ServerState {
+    options: ServerOptions,
+    cache: Cache,
+    stats: Stats,
+    resolutions: ResolutionLimit,
+    tcp_connections: AtomicUsize,
+}
+
+/// Counts an open TCP connection for as long as it is held.
+struct TcpConnectionSlot<'a> {
+    count: &'a AtomicUsize,
+}
+
+impl ServerState {
+    fn new(options: &ServerOptions) -> ServerState {
+        ServerState {
+            options: options.clone(),
+            cache: Cache::new(options.cache_size),
+            stats: Stats::new(),
+            resolutions: ResolutionLimit::new(options.max_recursive_resolutions),
+            tcp_connections: AtomicUsize::new(0),
         }
+    }
 
-        let new_ns_name = match response.get_unresolved_ns(qname) {
-            Some(x) => x,
-            None => return Ok(response.clone())
-        };
+    /// Takes one of the `max_tcp_connections` slots, if any is free.
+    fn open_tcp_connection(&self) -> Option<TcpConnectionSlot<'_>> {
+        let count = &self.tcp_connections;
+        let max = self.options.max_tcp_connections;
+        count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()?;
 
-        let recursive_response = recursive_lookup(&new_ns_name, QueryType::A)?;
-        
-        // pick a random IP from the result, and restart the loop
-        // if no such record is available, we return the last result we got
-        if let Some(new_ns) = recursive_response.get_random_a() {
-            ns = new_ns.clone();
-        } else {
-            return Ok(response.clone())
+        Some(TcpConnectionSlot { count })
+    }
+}
+
+impl Drop for TcpConnectionSlot<'_> {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A UDP query waiting for a worker, along with the socket to answer on.
+struct UdpJob<'a> {
+    data: Vec<u8>,
+    src: SocketAddr,
+    socket: &'a UdpSocket,
+}
+
+/// The lowest source port used for outgoing queries, leaving out the well
+/// known ports.
+const MIN_SOURCE_PORT: u16 = 1024;
+
+/// How many random source ports to try before leaving the choice to the OS.
+const SOURCE_PORT_ATTEMPTS: usize = 10;
+
+/// Binds a UDP socket on a random port to send a query to `server` from.
+/// Together with the random query ID this leaves a spoofer about 2^32
+/// combinations to guess rather than 2^16.
+fn bind_random_port(server: SocketAddr) -> Result<UdpSocket> {
+    let ip: IpAddr = if server.is_ipv4() {
+        [0, 0, 0, 0].into()
+    } else {
+        [0u16; 8].into()
+    };
+
+    for _ in 0..SOURCE_PORT_ATTEMPTS {
+        let port = thread_rng().gen_range(MIN_SOURCE_PORT as u32, 65536) as u16;
+        match UdpSocket::bind((ip, port)) {
+            Ok(socket) => return Ok(socket),
+            Err(e) if e.kind() == ErrorKind::AddrInUse => continue,
+            Err(e) => return Err(e),
         }
     }
+
+    // Most systems pick ephemeral ports at random as well.
+    UdpSocket::bind((ip, 0))
 }
 
-pub fn main() {
-    // Forward queries to Google's public DNS
-    let server = ("8.8.8.8", 53);
+/// Whether `response` answers `query`: the ID and question must be the same.
+/// With case randomization the name has to match exactly, since a forged
+/// response is unlikely to have guessed the case we used.
+fn is_response_to(response: &DnsPacket, query: &DnsPacket, options: &LookupOptions) -> bool {
+    if !response.header.response || response.header.id != query.header.id {
+        return false;
+    }
 
-    // Bind a UDP socket on port 2053
-    let socket = UdpSocket::bind(("0.0.0.0", 2053)).unwrap();
+    match (response.questions.as_slice(), query.questions.as_slice()) {
+        ([answered], [asked]) => {
+            let same_name = if options.randomize_case {
+                answered.name.eq_exact(&asked.name)
+            } else {
+                answered.name == asked.name
+            };
+            same_name && answered.qtype == asked.qtype && answered.qclass == asked.qclass
+        }
+        _ => false,
+    }
+}
 
-    // Queries are handled sequentially.
-    loop {
+/// The time left until `deadline`, or a `TimedOut` error if it has passed.
+fn time_left(deadline: Instant, what: &str) -> Result<Duration> {
+    let now = Instant::now();
+    if now >= deadline {
+        return Err(Error::new(
+            ErrorKind::TimedOut,
+            format!("{} timed out", what),
+        ));
+    }
+    Ok(deadline - now)
+}
 
-        // With a socket ready we can read a packet. This will block until
-        // one is received.
-        let mut req_buffer = BytePacketBuffer::new();
-        let (_, src) = match socket.recv_from(&mut req_buffer.buf) {
-            Ok(x) => x,
-            Err(e) => {
-                println!("Failed to read from UDP socket: {:#?}", e); continue; }
-        };
+fn is_timeout(e: &Error) -> bool {
+    e.kind() == ErrorKind::TimedOut || e.kind() == ErrorKind::WouldBlock
+}
+
+/// Sends a single query to `server` and waits for the answer, for at most
+/// `options.timeout` and never past `deadline`.
+fn lookup(
+    qname: &Name,
+    qtype: QueryType,
+    server: SocketAddr,
+    options: &LookupOptions,
+    deadline: Instant,
+) -> Result<DnsPacket> {
+    let socket = bind_random_port(server)?;
+    let query_deadline = deadline.min(Instant::now() + options.timeout);
+
+    let sent_name = if options.randomize_case {
+        qname.with_random_case()
+    } else {
+        qname.clone()
+    };
+
+    // EDNS0 lets the server send larger responses, and explain failures
+    // with Extended DNS Errors.
+    let mut packet =
+        DnsPacket::query(&sent_name, qtype).with_edns(DEFAULT_EDNS_PAYLOAD_SIZE, false);
+
+    let mut req_buffer = BytePacketBuffer::new();
+    packet.write(&mut req_buffer)?;
+    socket.send_to(&req_buffer.buf[0..req_buffer.pos], server)?;
 
-        // Use match to safely unwrap the Result. If everything is as expected, the
-        // raw bytes are returned, and if not it'll abort by restarting the loop
-        // and waiting for the next request.
+    // Anything that isn't from the server, or doesn't answer our query, may
+    // be an attempt at spoofing, so it's dropped and we keep waiting. It
+    // doesn't extend the time we wait for, though.
+    let mut response = loop {
+        socket.set_read_timeout(Some(time_left(query_deadline, "Query")?))?;
 
-        // Next parse the raw bytes into a DnsPacket.
-        let request = match DnsPacket::from_buffer(&mut req_buffer) {
+        let mut recv_buf = [0; DEFAULT_EDNS_PAYLOAD_SIZE as usize];
+        let (len, src) = match socket.recv_from(&mut recv_buf) {
             Ok(x) => x,
-            Err(e) => {
-                println!("Failed to parse UDP query packet: {:#?}", e);
-                continue;
+            Err(ref e) if is_timeout(e) => {
+                return Err(Error::new(ErrorKind::TimedOut, "Query timed out"))
             }
+            Err(e) => return Err(e),
         };
+        if src != server {
+            log_info!("Ignoring response to {} from unexpected {}", server, src);
+            continue;
+        }
 
-        // Create and init the response packet
-        let mut packet = DnsPacket::new();
-        packet.header.id = request.header.id;
-        packet.header.recursion_desired = true;
-        packet.header.recursion_available = true;
-        packet.header.response = true;
-
-        // Make sure a question is actually present.
-        // If not, we return `FORMERR` to indicate that the sender made something wrong.
-        if request.questions.is_empty() {
-            packet.header.rescode = ResultCode::FORMERR;
-        } else {
-            let question = &request.questions[0];
-            println!("Received query: {:#?}", question);
-
-            // Query can be forwarded to the target server.
-            // It's possible that the query will fail, in which case we can use the
-            // SERVFAIL response code.
-            if let Ok(result) = recursive_lookup(&question.name, question.qtype) {
-                packet.questions.push(question.clone());
-                packet.header.rescode = result.header.rescode;
-
-                for rec in result.answers {
-                    println!("Answer: {:#?}", rec);
-                    packet.answers.push(rec);
-                }
-                for rec in result.authorities {
-                    println!("Authority: {:#?}", rec);
-                    packet.authorities.push(rec);
+        let data = &recv_buf[..len];
+        let response = match DnsPacket::from_buffer(&mut VectorPacketBuffer::from_bytes(data)) {
+            Ok(x) => x,
+            // Only give up on the server if the response really seems to
+            // be from it.
+            Err(e) => match DnsPacketRef::new(data) {
+                Ok(ref x) if x.header.id == packet.header.id => return Err(e.into()),
+                _ => {
+                    log_info!("Ignoring malformed response from {}: {}", src, e);
+                    continue;
                 }
-                for rec in result.resources {
-                    println!("Resource: {:#?}", rec);
-                    packet.resources.push(rec);
+            },
+        };
+        if !is_response_to(&response, &packet, options) {
+            log_info!(
+                "Ignoring response from {} that doesn't match query {} {}",
+                src,
+                sent_name,
+                qtype
+            );
+            continue;
+        }
+
+        break response;
+    };
+
+    // The answer didn't fit in a UDP response, so ask again over TCP. That
+    // counts as a new query as far as the timeout goes.
+    if response.header.truncated_message {
+        let query_deadline = deadline.min(Instant::now() + options.timeout);
+
+        let mut stream =
+            TcpStream::connect_timeout(&server, time_left(query_deadline, "TCP connection")?)?;
+        stream.set_write_timeout(Some(time_left(query_deadline, "TCP query")?))?;
+        write_tcp_message(&mut stream, &req_buffer.buf[0..req_buffer.pos])?;
+
+        let data = read_tcp_message(&mut stream, query_deadline)?;
+        response = DnsPacket::from_buffer(&mut VectorPacketBuffer::from_bytes(&data))?;
+        if !is_response_to(&response, &packet, options) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("TCP response doesn't match query {} {}", sent_name, qtype),
+            ));
+        }
+    }
+
+    Ok(response)
+}
+
+/// Queries `server`, sending the query again if it times out, up to
+/// `options.retries` times.
+fn lookup_with_retries(
+    qname: &Name,
+    qtype: QueryType,
+    server: SocketAddr,
+    options: &LookupOptions,
+    deadline: Instant,
+) -> Result<DnsPacket> {
+    let mut attempt = 0;
+    loop {
+        match lookup(qname, qtype, server, options, deadline) {
+            Err(ref e)
+                if is_timeout(e) && attempt < options.retries && Instant::now() < deadline =>
+            {
+                attempt += 1;
+                log_debug!(
+                    "No answer from {}, retrying ({}/{})",
+                    server,
+                    attempt,
+                    options.retries
+                );
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Asks each of `servers` in turn until one gives a usable answer. A server
+/// that times out, can't be reached or answers SERVFAIL or REFUSED is
+/// skipped in favor of the next. If none of them answers, the last failure
+/// is returned, with an Extended DNS Error added if it was a response.
+fn lookup_any(
+    qname: &Name,
+    qtype: QueryType,
+    servers: &[SocketAddr],
+    options: &LookupOptions,
+    deadline: Instant,
+) -> Result<DnsPacket> {
+    let mut last_result = Err(Error::new(ErrorKind::NotFound, "No servers to ask"));
+    for &server in servers {
+        time_left(deadline, "Resolution")?;
+        log_debug!(
+            "Attempting lookup of {:?} {} with ns {}",
+            qtype,
+            qname,
+            server
+        );
+
+        match lookup_with_retries(qname, qtype, server, options, deadline) {
+            Ok(response) => {
+                let rescode = response.rescode();
+                if rescode != ResultCode::SERVFAIL && rescode != ResultCode::REFUSED {
+                    return Ok(response);
                 }
-            } else {
-                packet.header.rescode = ResultCode::SERVFAIL;
+                log_debug!("{} answered {}, trying the next server", server, rescode);
+                last_result = Ok(response);
+            }
+            Err(e) => {
+                log_debug!("Lookup at {} failed: {}", server, e);
+                last_result = Err(Error::new(
+                    e.kind(),
+                    format!("Lookup at {} failed: {}", server, e),
+                ));
             }
+        }
+    }
+
+    if let Ok(ref mut response) = last_result {
+        let rescode = response.rescode();
+        response.add_extended_error(ExtendedError::new(
+            ExtendedErrorCode::NoReachableAuthority,
+            format!(
+                "No server gave an answer, the last one answered {}",
+                rescode
+            ),
+        ));
+    }
+
+    last_result
+}
+
+/// Reads a message prefixed with its 2 byte length, as used over TCP. The
+/// whole message has to arrive by `deadline`, so that a peer can't hold the
+/// connection by sending a byte at a time.
+fn read_tcp_message(stream: &mut TcpStream, deadline: Instant) -> Result<Vec<u8>> {
+    let mut len_buf = [0; 2];
+    read_exact_by(stream, &mut len_buf, deadline)?;
+    let len = ((len_buf[0] as usize) << 8) | (len_buf[1] as usize);
+
+    let mut data = vec![0; len];
+    read_exact_by(stream, &mut data, deadline)?;
+    Ok(data)
+}
 
-            // Encode response and send it.
-            let mut res_buffer = BytePacketBuffer::new();
-            match packet.write(&mut res_buffer) {
-                Ok(_) => {},
+/// Like `read_exact`, but fails with `TimedOut` once `deadline` passes.
+fn read_exact_by(stream: &mut TcpStream, buf: &mut [u8], deadline: Instant) -> Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        stream.set_read_timeout(Some(time_left(deadline, "TCP read")?))?;
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(ref e) if is_timeout(e) => {
+                return Err(Error::new(ErrorKind::TimedOut, "TCP read timed out"))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Writes a message prefixed with its 2 byte length, in a single write so
+/// that the prefix doesn't go out in a packet of its own.
+fn write_tcp_message<W: Write>(stream: &mut W, data: &[u8]) -> Result<()> {
+    let mut message = Vec::with_capacity(data.len() + 2);
+    message.push((data.len() >> 8) as u8);
+    message.push((data.len() & 0xFF) as u8);
+    message.extend_from_slice(data);
+    stream.write_all(&message)
+}
+
+fn recursive_lookup(
+    qname: &Name,
+    qtype: QueryType,
+    root_servers: &[IpAddr],
+    options: &LookupOptions,
+    deadline: Instant,
+) -> Result<DnsPacket> {
+    // start with the root servers, in random order so that the load is
+    // spread between them
+    let mut servers = root_servers.to_vec();
+
+    loop {
+        servers.shuffle(&mut thread_rng());
+        let addrs: Vec<SocketAddr> = servers.iter().map(|&ip| SocketAddr::new(ip, 53)).collect();
+
+        // send query to the servers for the zone, until one answers
+        let response = lookup_any(qname, qtype, &addrs, options, deadline)?;
+
+        // if there are entries in the answer section, and no errors, we're done!
+        if !response.answers.is_empty() && response.rescode() == ResultCode::NOERROR {
+            return Ok(response);
+        }
+
+        if response.rescode() == ResultCode::NXDOMAIN {
+            return Ok(response);
+        }
+
+        // follow the referral, preferring the nameservers that came with
+        // their addresses
+        let glued = response.get_resolved_nss(qname);
+        if !glued.is_empty() {
+            servers = glued.into_iter().map(IpAddr::V4).collect();
+            continue;
+        }
+
+        let mut ns_names = response.get_unresolved_nss(qname);
+        if ns_names.is_empty() {
+            return Ok(response);
+        }
+
+        // Look up the nameservers' addresses, one at a time until one of
+        // them resolves. If none do, no authority for the name can be
+        // reached, which is a failure rather than an answer.
+        ns_names.shuffle(&mut thread_rng());
+        servers.clear();
+        for ns_name in &ns_names {
+            match recursive_lookup(ns_name, QueryType::A, root_servers, options, deadline) {
+                Ok(ns_response) => {
+                    servers = ns_response
+                        .get_a_addrs()
+                        .into_iter()
+                        .map(IpAddr::V4)
+                        .collect();
+                    if !servers.is_empty() {
+                        break;
+                    }
+                }
                 Err(e) => {
-                    println!("Failed to encode UDP response packet: {:#?}", e);
+                    time_left(deadline, "Resolution")?;
+                    log_debug!("Failed to resolve nameserver {}: {}", ns_name, e);
                 }
             }
+        }
+        if servers.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No nameserver for {} could be resolved", qname),
+            ));
+        }
+    }
+}
 
-            let len = res_buffer.pos();
-            let data = match res_buffer.get_range(0, len) {
-                Ok(x) => x,
-                Err(e) => {
-                    println!("Failed to retrieve repsonse buffer: {:#?}", e);
-                    continue;
+/// Resolves a question the way the server is configured to, giving up at
+/// `deadline`.
+fn resolve(
+    question: &DnsQuestion,
+    options: &ServerOptions,
+    deadline: Instant,
+) -> Result<DnsPacket> {
+    match options.mode {
+        ResolverMode::Recursive { ref root_servers } => recursive_lookup(
+            &question.name,
+            question.qtype,
+            root_servers,
+            &options.lookup,
+            deadline,
+        ),
+        // Pass the query to each forwarder in turn, until one answers.
+        ResolverMode::Forwarding { ref forwarders } => lookup_any(
+            &question.name,
+            question.qtype,
+            forwarders,
+            &options.lookup,
+            deadline,
+        ),
+    }
+}
+
+/// Answers the conventional `version.bind` and `version.server` TXT queries
+/// in the CHAOS class.
+fn chaos_lookup(question: &DnsQuestion) -> Option<DnsRecord> {
+    if question.qclass != QueryClass::CH || question.qtype != QueryType::TXT {
+        return None;
+    }
+    let is_version = ["version.bind", "version.server"]
+        .iter()
+        .any(|name| name.parse::<Name>().ok().as_ref() == Some(&question.name));
+    if !is_version {
+        return None;
+    }
+
+    let version = format!("dns-server {}", env!("CARGO_PKG_VERSION"));
+    Some(DnsRecord::TXT {
+        domain: question.name.clone(),
+        data: vec![version.into_bytes()],
+        class: QueryClass::CH,
+        ttl: 0,
+    })
+}
+
+/// Encodes a response. If it doesn't fit in what the client can receive, just
+/// the header and question are sent with the TC bit set so that the client
+/// retries over TCP. If it can't be encoded at all, SERVFAIL is sent instead.
+fn encode_response(packet: &mut DnsPacket, max_size: usize) -> Option<Vec<u8>> {
+    let mut res_buffer = VectorPacketBuffer::with_max_size(max_size);
+    if let Err(e) = packet.write(&mut res_buffer) {
+        if e.kind == DnsErrorKind::BufferFull {
+            log_info!("Response exceeds {} bytes, truncating: {}", max_size, e);
+            packet.header.truncated_message = true;
+        } else {
+            log_error!("Failed to encode response packet: {}", e);
+            packet.header.rescode = ResultCode::SERVFAIL;
+        }
+
+        packet.answers.clear();
+        packet.authorities.clear();
+        packet
+            .resources
+            .retain(|rec| matches!(rec, DnsRecord::OPT { .. }));
+
+        res_buffer = VectorPacketBuffer::with_max_size(max_size);
+        if let Err(e) = packet.write(&mut res_buffer) {
+            log_error!("Failed to encode response packet: {}", e);
+            return None;
+        }
+    }
+
+    let len = res_buffer.pos();
+    res_buffer.buf.truncate(len);
+    Some(res_buffer.buf)
+}
+
+/// Handles a standard QUERY, by resolving it recursively or answering it
+/// locally.
+fn handle_query(request: &DnsPacket, state: &ServerState) -> DnsPacket {
+    // Create and init the response packet, which echoes the question.
+    let mut packet = DnsPacket::response_to(request).with_recursion_available(true);
+
+    // Make sure a question is actually present.
+    // If not, we return `FORMERR` to indicate that the sender made something wrong.
+    if request.questions.is_empty() {
+        packet.header.rescode = ResultCode::FORMERR;
+        return packet;
+    }
+
+    let question = &request.questions[0];
+    log_info!("Received query: {}", question);
+
+    // Only the Internet class is resolved recursively. CHAOS queries
+    // for the server version are answered locally, anything else is
+    // refused.
+    //
+    // Internet queries can be forwarded to the target server.
+    // It's possible that the query will fail, in which case we can use the
+    // SERVFAIL response code.
+    if question.qclass != QueryClass::IN {
+        match chaos_lookup(question) {
+            Some(rec) => packet.answers.push(rec),
+            None => packet.header.rescode = ResultCode::REFUSED,
+        }
+        return packet;
+    }
+
+    let result = match state.cache.lookup(question) {
+        Some(x) => {
+            Stats::count(&state.stats.cache_hits);
+            x
+        }
+        None => {
+            // The time budget starts now, so that it covers waiting for
+            // other resolutions to finish as well.
+            let deadline = Instant::now() + state.options.lookup.time_budget;
+            let _permit = match state.resolutions.acquire(deadline) {
+                Some(x) => x,
+                None => {
+                    log_error!("Gave up waiting to resolve {}", question);
+                    Stats::count(&state.stats.failed_lookups);
+                    packet.header.rescode = ResultCode::SERVFAIL;
+                    packet.add_extended_error(ExtendedError::new(
+                        ExtendedErrorCode::Other,
+                        "Too many lookups in progress",
+                    ));
+                    return packet;
                 }
             };
+            Stats::count(&state.stats.recursive_lookups);
 
-            match socket.send_to(&data, src) {
-                Ok(_) => {},
+            match resolve(question, &state.options, deadline) {
+                Ok(x) => {
+                    state.cache.store(question, &x);
+                    x
+                }
                 Err(e) => {
-                    println!("Failed to send response buffer: {:#?}", e);
+                    log_error!("Failed to resolve {}: {}", question, e);
+                    Stats::count(&state.stats.failed_lookups);
+                    packet.header.rescode = ResultCode::SERVFAIL;
+                    packet.add_extended_error(lookup_error(&e));
+                    return packet;
                 }
             }
         }
+    };
+
+    // Extended codes describe the EDNS0 exchange with the upstream server, so
+    // they mean nothing to our client.
+    let rescode = result.rescode();
+    packet.header.rescode = if rescode.is_extended() {
+        ResultCode::SERVFAIL
+    } else {
+        rescode
+    };
+
+    // Pass on the upstream server's explanation of a failure.
+    if rescode != ResultCode::NOERROR {
+        for err in result.extended_errors() {
+            log_info!("Upstream error: {}", err);
+            packet.add_extended_error(err);
+        }
+    }
+
+    for rec in result.answers {
+        log_debug!("Answer: {}", rec);
+        packet.answers.push(rec);
+    }
+    for rec in result.authorities {
+        log_debug!("Authority: {}", rec);
+        packet.authorities.push(rec);
+    }
+    for rec in result.resources {
+        // The upstream OPT record describes the upstream server's EDNS0
+        // parameters, not ours.
+        if let DnsRecord::OPT { .. } = rec {
+            continue;
+        }
+        log_debug!("Resource: {}", rec);
+        packet.resources.push(rec);
+    }
+
+    packet
+}
+
+/// Explains a failed recursive lookup with an Extended DNS Error.
+fn lookup_error(e: &Error) -> ExtendedError {
+    let code = match e.kind() {
+        ErrorKind::TimedOut
+        | ErrorKind::WouldBlock
+        | ErrorKind::ConnectionRefused
+        | ErrorKind::NotFound => ExtendedErrorCode::NoReachableAuthority,
+        _ => ExtendedErrorCode::NetworkError,
+    };
+
+    ExtendedError::new(code, e.to_string())
+}
+
+/// Processing for the opcodes other than QUERY that the server can support,
+/// such as zone change notifications from a primary server. The default
+/// implementations answer NOTIMP; implement the methods that are needed and
+/// pass the handler to `run`.
+///
+/// The returned packet is sent as the response, after EDNS0 is added if the
+/// request used it. `DnsPacket::response_to` is a good starting point.
+pub trait RequestHandler {
+    /// Handles a NOTIFY (RFC 1996) request.
+    fn notify(&self, request: &DnsPacket, _src: SocketAddr) -> DnsPacket {
+        DnsPacket::response_to(request).with_rescode(ResultCode::NOTIMP)
+    }
+
+    /// Handles an UPDATE (RFC 2136) request.
+    fn update(&self, request: &DnsPacket, _src: SocketAddr) -> DnsPacket {
+        DnsPacket::response_to(request).with_rescode(ResultCode::NOTIMP)
+    }
+}
+
+/// A handler that supports none of the optional opcodes.
+pub struct DefaultHandler;
+
+impl RequestHandler for DefaultHandler {}
+
+pub fn main() {
+    if let Err(e) = run(&DefaultHandler, &ServerOptions::default()) {
+        eprintln!("{}", e);
+    }
+}
+
+/// Runs the server, passing NOTIFY and UPDATE requests to `handler` and
+/// resolving queries as set in `options`. Queries are accepted over both UDP
+/// and TCP on each of the listen addresses, and answered concurrently.
+///
+/// Only returns if a listen address can't be bound.
+pub fn run<H: RequestHandler + Sync>(handler: &H, options: &ServerOptions) -> Result<()> {
+    options.log_level.set();
+
+    // Bind a UDP socket and a TCP listener on each address
+    let mut sockets = Vec::new();
+    let mut listeners = Vec::new();
+    for &addr in &options.listen {
+        let bind_error = |e: Error| Error::new(e.kind(), format!("Failed to bind {}: {}", addr, e));
+        sockets.push(UdpSocket::bind(addr).map_err(bind_error)?);
+        listeners.push(TcpListener::bind(addr).map_err(bind_error)?);
+        log_info!("Listening on {}", addr);
+    }
+
+    let state = ServerState::new(options);
+    let state = &state;
+
+    thread::scope(|scope| {
+        for listener in listeners {
+            scope.spawn(move || {
+                // Each connection gets its own thread, so that an idle client
+                // doesn't hold up the others, up to a limit on the number of
+                // connections. Past that, new connections are closed.
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => match state.open_tcp_connection() {
+                            Some(slot) => {
+                                scope.spawn(move || {
+                                    serve_tcp(stream, handler, state);
+                                    drop(slot);
+                                });
+                            }
+                            None => {
+                                log_info!("Closing TCP connection, too many are open");
+                                Stats::count(&state.stats.dropped_queries);
+                            }
+                        },
+                        Err(e) => log_error!("Failed to accept TCP connection: {:#?}", e),
+                    }
+                }
+            });
+        }
+
+        scope.spawn(move || loop {
+            thread::sleep(STATS_INTERVAL);
+            log_info!("Stats: {}, {} cached", state.stats, state.cache.len());
+        });
+
+        serve_udp(&sockets, handler, state);
+    });
+
+    Ok(())
+}
+
+/// Reads queries from the UDP sockets and hands them to a pool of worker
+/// threads, which answer them on the socket they arrived on.
+fn serve_udp<H: RequestHandler + Sync>(sockets: &[UdpSocket], handler: &H, state: &ServerState) {
+    let (sender, receiver) = mpsc::sync_channel::<UdpJob>(UDP_QUEUE_LEN);
+    let receiver = Mutex::new(receiver);
+    let receiver = &receiver;
+
+    thread::scope(|scope| {
+        for _ in 0..state.options.worker_threads.max(1) {
+            scope.spawn(move || loop {
+                // Only hold the lock while waiting for a job, not while
+                // answering it.
+                let job = match receiver.lock().unwrap().recv() {
+                    Ok(x) => x,
+                    Err(_) => return,
+                };
+
+                let response = match handle_request(&job.data, job.src, false, handler, state) {
+                    Some(x) => x,
+                    None => continue,
+                };
+
+                if let Err(e) = job.socket.send_to(&response, job.src) {
+                    log_error!("Failed to send response buffer: {:#?}", e);
+                }
+            });
+        }
+
+        for socket in sockets {
+            let sender = sender.clone();
+            scope.spawn(move || loop {
+                // With a socket ready we can read a packet. This will block
+                // until one is received. EDNS0 clients may send more than 512
+                // bytes.
+                let mut recv_buf = [0; DEFAULT_EDNS_PAYLOAD_SIZE as usize];
+                let (len, src) = match socket.recv_from(&mut recv_buf) {
+                    Ok(x) => x,
+                    Err(e) => {
+                        log_error!("Failed to read from UDP socket: {:#?}", e);
+                        continue;
+                    }
+                };
+                Stats::count(&state.stats.udp_queries);
+
+                // If the workers are this far behind, the client will have
+                // given up on the query by the time it's answered.
+                let job = UdpJob {
+                    data: recv_buf[..len].to_vec(),
+                    src,
+                    socket,
+                };
+                if let Err(TrySendError::Full(job)) = sender.try_send(job) {
+                    log_info!("Dropping query from {}, all workers are busy", job.src);
+                    Stats::count(&state.stats.dropped_queries);
+                }
+            });
+        }
+        drop(sender);
+    });
+}
+
+/// Answers the queries arriving on a TCP connection, in order, until the
+/// client closes it or takes longer than `TCP_IDLE_TIMEOUT` to send a query.
+fn serve_tcp<H: RequestHandler>(mut stream: TcpStream, handler: &H, state: &ServerState) {
+    let src = match stream.peer_addr() {
+        Ok(x) => x,
+        Err(e) => {
+            log_error!("Failed to get TCP peer address: {:#?}", e);
+            return;
+        }
+    };
+    // A client that doesn't read its responses mustn't block us either.
+    if let Err(e) = stream.set_write_timeout(Some(TCP_IDLE_TIMEOUT)) {
+        log_error!("Failed to set TCP timeout: {:#?}", e);
+        return;
+    }
+
+    loop {
+        // A clean close between messages, or an idle client, just ends the
+        // connection.
+        let request = match read_tcp_message(&mut stream, Instant::now() + TCP_IDLE_TIMEOUT) {
+            Ok(x) => x,
+            Err(e) => {
+                if e.kind() != ErrorKind::UnexpectedEof
+                    && e.kind() != ErrorKind::WouldBlock
+                    && e.kind() != ErrorKind::TimedOut
+                {
+                    log_error!("Failed to read from TCP connection: {:#?}", e);
+                }
+                return;
+            }
+        };
+
+        Stats::count(&state.stats.tcp_queries);
+
+        let response = match handle_request(&request, src, true, handler, state) {
+            Some(x) => x,
+            None => continue,
+        };
+
+        if let Err(e) = write_tcp_message(&mut stream, &response) {
+            log_error!("Failed to send TCP response: {:#?}", e);
+            return;
+        }
     }
-}
\ No newline at end of file
+}
+
+/// Parses a request, processes it and encodes the response. Returns `None` if
+/// nothing should be sent back.
+fn handle_request<H: RequestHandler>(
+    data: &[u8],
+    src: SocketAddr,
+    tcp: bool,
+    handler: &H,
+    state: &ServerState,
+) -> Option<Vec<u8>> {
+    let allowed = state.options.acl.allows(src.ip());
+
+    // Next parse the raw bytes into a DnsPacket. A malformed query is
+    // answered with FORMERR, and one we failed to parse for reasons of our
+    // own with SERVFAIL, provided there's at least a header to reply to.
+    let mut req_buffer = VectorPacketBuffer::from_bytes(data);
+    let request = match DnsPacket::from_buffer(&mut req_buffer) {
+        Ok(x) => x,
+        Err(e) => {
+            log_info!("Failed to parse query packet: {}", e);
+
+            let header = DnsPacketRef::new(data).ok()?.header;
+            if !allowed || header.response {
+                return None;
+            }
+
+            let rescode = if e.is_malformed_input() {
+                ResultCode::FORMERR
+            } else {
+                ResultCode::SERVFAIL
+            };
+            let request = DnsPacket {
+                header,
+                ..DnsPacket::default()
+            };
+            let mut packet = DnsPacket::response_to(&request).with_rescode(rescode);
+            return encode_response(&mut packet, 512);
+        }
+    };
+
+    // Responses are never answered, or two servers could keep answering
+    // each other, and a spoofed response could start such a loop.
+    if request.header.response {
+        log_info!("Ignoring response from {}", src);
+        return None;
+    }
+
+    // Clients outside the ACL learn nothing but that they aren't welcome.
+    if !allowed {
+        log_info!("Refusing {} request from {}", request.header.opcode, src);
+        let mut packet = DnsPacket::response_to(&request).with_rescode(ResultCode::REFUSED);
+        return encode_response(&mut packet, 512);
+    }
+
+    // RFC 6891 allows a single OPT record, and we only speak version 0 of
+    // EDNS. The response to a newer version says which one we do speak.
+    let opt_count = request
+        .resources
+        .iter()
+        .filter(|rec| matches!(rec, DnsRecord::OPT { .. }))
+        .count();
+    let edns_version = request.edns_version().unwrap_or(0);
+
+    let mut packet = match request.header.opcode {
+        _ if opt_count > 1 => {
+            log_info!("Received request with {} OPT records", opt_count);
+            DnsPacket::response_to(&request).with_rescode(ResultCode::FORMERR)
+        }
+        _ if edns_version > 0 => {
+            log_info!("Received request for EDNS version {}", edns_version);
+            DnsPacket::response_to(&request).with_rescode(ResultCode::BADVERS)
+        }
+        Opcode::QUERY => handle_query(&request, state),
+        Opcode::NOTIFY => handler.notify(&request, src),
+        Opcode::UPDATE => handler.update(&request, src),
+        opcode => {
+            log_info!("Received unsupported {} request", opcode);
+            DnsPacket::response_to(&request).with_rescode(ResultCode::NOTIMP)
+        }
+    };
+
+    // If the client used EDNS0 we answer with EDNS0 as well, and may send
+    // up to as many bytes as it told us it can receive. Over TCP the size is
+    // only limited by the length prefix.
+    let max_size = match request.edns_payload_size() {
+        Some(size) => {
+            if packet.edns_payload_size().is_none() {
+                packet.set_edns(DEFAULT_EDNS_PAYLOAD_SIZE, false);
+            }
+            (size as usize).clamp(512, DEFAULT_EDNS_PAYLOAD_SIZE as usize)
+        }
+        None => {
+            // Clients without EDNS0 can't receive an OPT record, nor the
+            // extended RCODEs and errors it carries.
+            if packet.rescode().is_extended() {
+                packet.set_rescode(ResultCode::SERVFAIL);
+            }
+            packet.clear_edns();
+            512
+        }
+    };
+    let max_size = if tcp { MAX_PACKET_SIZE } else { max_size };
+
+    encode_response(&mut packet, max_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 53000))
+    }
+
+    fn encode(packet: &mut DnsPacket) -> Vec<u8> {
+        let mut buffer = VectorPacketBuffer::new();
+        packet.write(&mut buffer).unwrap();
+        buffer.buf
+    }
+
+    /// Sends `data` through `handle_request` as a UDP query.
+    fn handle(data: &[u8]) -> Option<DnsPacket> {
+        let state = ServerState::new(&ServerOptions::default());
+        let response = handle_request(data, client(), false, &DefaultHandler, &state)?;
+        Some(DnsPacket::from_buffer(&mut VectorPacketBuffer::from_bytes(&response)).unwrap())
+    }
+
+    /// A query the server answers by itself, without any network access.
+    fn version_query() -> DnsPacket {
+        DnsPacket::query(&"version.bind".parse().unwrap(), QueryType::TXT)
+            .with_class(QueryClass::CH)
+            .with_id(4321)
+    }
+
+    #[test]
+    fn resolution_limit_gives_up_at_deadline() {
+        let limit = ResolutionLimit::new(1);
+        let soon = || Instant::now() + Duration::from_millis(20);
+
+        let permit = limit.acquire(soon()).unwrap();
+        let start = Instant::now();
+        assert!(limit.acquire(soon()).is_none());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        drop(permit);
+        assert!(limit.acquire(soon()).is_some());
+    }
+
+    #[test]
+    fn answers_queries() {
+        let response = handle(&encode(&mut version_query())).unwrap();
+        assert_eq!(response.header.id, 4321);
+        assert_eq!(response.rescode(), ResultCode::NOERROR);
+        assert_eq!(response.answers.len(), 1);
+    }
+
+    #[test]
+    fn answers_malformed_queries_with_formerr() {
+        let mut data = encode(&mut version_query());
+        data.truncate(data.len() - 3);
+
+        let response = handle(&data).unwrap();
+        assert_eq!(response.header.id, 4321);
+        assert_eq!(response.rescode(), ResultCode::FORMERR);
+        assert!(response.questions.is_empty());
+    }
+
+    #[test]
+    fn ignores_malformed_responses_and_runt_packets() {
+        let mut packet = version_query();
+        packet.header.response = true;
+        let mut data = encode(&mut packet);
+        data.truncate(data.len() - 3);
+        assert!(handle(&data).is_none());
+
+        assert!(handle(&data[..11]).is_none());
+    }
+
+    #[test]
+    fn ignores_responses() {
+        let mut packet = DnsPacket::response_to(&version_query());
+        assert!(handle(&encode(&mut packet)).is_none());
+
+        let mut packet = packet.with_rescode(ResultCode::SERVFAIL);
+        assert!(handle(&encode(&mut packet)).is_none());
+    }
+
+    #[test]
+    fn answers_edns_queries_with_edns() {
+        let mut query = version_query().with_edns(1232, false);
+        let response = handle(&encode(&mut query)).unwrap();
+        assert_eq!(response.rescode(), ResultCode::NOERROR);
+        assert_eq!(response.edns_version(), Some(0));
+        assert_eq!(response.answers.len(), 1);
+    }
+
+    #[test]
+    fn answers_newer_edns_versions_with_badvers() {
+        let mut query = version_query().with_edns(1232, false);
+        if let DnsRecord::OPT { ref mut flags, .. } = query.resources[0] {
+            *flags |= 1 << 16;
+        }
+
+        let response = handle(&encode(&mut query)).unwrap();
+        assert_eq!(response.header.id, 4321);
+        assert_eq!(response.rescode(), ResultCode::BADVERS);
+        assert_eq!(response.edns_version(), Some(0));
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn answers_multiple_opt_records_with_formerr() {
+        let mut query = version_query().with_edns(1232, false);
+        query.resources.push(query.resources[0].clone());
+
+        let response = handle(&encode(&mut query)).unwrap();
+        assert_eq!(response.rescode(), ResultCode::FORMERR);
+        assert!(response.answers.is_empty());
+    }
+
+    #[test]
+    fn matches_responses_to_queries() {
+        let mut options = LookupOptions::default();
+        let query = DnsPacket::query(&"WwW.ExAmPlE.cOm".parse().unwrap(), QueryType::A).with_id(77);
+        let response = || DnsPacket::response_to(&query);
+        assert!(is_response_to(&response(), &query, &options));
+
+        let wrong_id = response().with_id(78);
+        assert!(!is_response_to(&wrong_id, &query, &options));
+
+        let mut not_a_response = response();
+        not_a_response.header.response = false;
+        assert!(!is_response_to(&not_a_response, &query, &options));
+
+        let mut wrong_type = response();
+        wrong_type.questions[0].qtype = QueryType::AAAA;
+        assert!(!is_response_to(&wrong_type, &query, &options));
+
+        let wrong_class = response().with_class(QueryClass::CH);
+        assert!(!is_response_to(&wrong_class, &query, &options));
+
+        let mut no_question = response();
+        no_question.questions.clear();
+        assert!(!is_response_to(&no_question, &query, &options));
+
+        let mut other_name = response();
+        other_name.questions[0].name = "www.example.net".parse().unwrap();
+        assert!(!is_response_to(&other_name, &query, &options));
+
+        // The case only has to match when it was randomized.
+        let mut other_case = response();
+        other_case.questions[0].name = "www.example.com".parse().unwrap();
+        assert!(is_response_to(&other_case, &query, &options));
+        options.randomize_case = true;
+        assert!(!is_response_to(&other_case, &query, &options));
+        assert!(is_response_to(&response(), &query, &options));
+    }
+
+    /// A server on localhost that answers every query with `rescode`.
+    fn failing_server(rescode: ResultCode) -> SocketAddr {
+        let socket = UdpSocket::bind(("127.0.0.1", 0)).unwrap();
+        let addr = socket.local_addr().unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 512];
+            while let Ok((len, src)) = socket.recv_from(&mut buf) {
+                let mut query = VectorPacketBuffer::from_bytes(&buf[..len]);
+                let query = DnsPacket::from_buffer(&mut query).unwrap();
+                let mut response = DnsPacket::response_to(&query).with_rescode(rescode);
+                socket.send_to(&encode(&mut response), src).unwrap();
+            }
+        });
+        addr
+    }
+
+    #[test]
+    fn explains_when_every_server_fails() {
+        let servers = [
+            failing_server(ResultCode::REFUSED),
+            failing_server(ResultCode::SERVFAIL),
+        ];
+        let options = ServerOptions {
+            mode: ResolverMode::Forwarding {
+                forwarders: servers.to_vec(),
+            },
+            ..ServerOptions::default()
+        };
+        let state = ServerState::new(&options);
+
+        let mut query = DnsPacket::query(&"example.com".parse().unwrap(), QueryType::A)
+            .with_id(99)
+            .with_edns(1232, false);
+        let response = handle_request(
+            &encode(&mut query),
+            client(),
+            false,
+            &DefaultHandler,
+            &state,
+        )
+        .unwrap();
+        let response =
+            DnsPacket::from_buffer(&mut VectorPacketBuffer::from_bytes(&response)).unwrap();
+
+        assert_eq!(response.rescode(), ResultCode::SERVFAIL);
+        let errors = response.extended_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, ExtendedErrorCode::NoReachableAuthority);
+    }
+}
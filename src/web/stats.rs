@@ -0,0 +1,42 @@
+//! Counters describing the work the server has done, shared by all the
+//! threads answering queries.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Default)]
+pub struct Stats {
+    pub udp_queries: AtomicU64,
+    pub tcp_queries: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub recursive_lookups: AtomicU64,
+    pub failed_lookups: AtomicU64,
+    pub dropped_queries: AtomicU64,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    /// Adds one to a counter.
+    pub fn count(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} UDP and {} TCP queries, {} cache hits, {} recursive lookups \
+             ({} failed), {} queries dropped",
+            self.udp_queries.load(Ordering::Relaxed),
+            self.tcp_queries.load(Ordering::Relaxed),
+            self.cache_hits.load(Ordering::Relaxed),
+            self.recursive_lookups.load(Ordering::Relaxed),
+            self.failed_lookups.load(Ordering::Relaxed),
+            self.dropped_queries.load(Ordering::Relaxed),
+        )
+    }
+}